@@ -1,8 +1,14 @@
+use crate::change::{content_hash, folder_stamp, write_if_changed, ChangeTracker, FileStamp};
+use crate::db::Backend;
+use crate::file_copy::ConsistentCopy;
+use crate::lif::{read_lif_dir, RaceResults};
 use crate::logic::*;
-use crate::writer::write_lynx_evt;
+use crate::source::{DataSource, PatSource};
+use crate::write_back::{plan_updates, write_results, ResultWriter, WriteBackPlan};
+use crate::writer::{render_lynx_evt, render_lynx_ppl, render_lynx_sch, render_race_json, ExportOptions};
 use anyhow::{Context, Result};
-use std::fs;
-use std::path::Path;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
 
 pub fn check_file_exists(path: &str) -> bool {
     Path::new(path).exists()
@@ -16,33 +22,46 @@ pub struct RaceData {
     pub competitors_in_comp: Vec<CompetitorInCompetition>,
 }
 
-pub fn fetch_race_data(
-    sync_location: &str, 
+impl RaceData {
+    /// Attaches the .LIF results to their lanes. The exports use them for
+    /// lanes GCPV has no result for yet.
+    pub fn merge_results(&mut self, results: &RaceResults) {
+        for lane in &mut self.lanes {
+            lane.lynx_result = results.for_lane(lane.id).cloned();
+        }
+    }
+}
+
+pub fn fetch_race_data<S: DataSource>(
+    source: &S,
     env_competition_id: Option<i32>
 ) -> Result<RaceData> {
-    if !check_file_exists(sync_location) {
-        anyhow::bail!("File {} not found", sync_location);
-    }
+    // Read every table once; all views below are built from this snapshot
+    let snapshot = Snapshot::load(source)?;
+    build_race_data(&snapshot, env_competition_id)
+}
 
+pub fn build_race_data(
+    snapshot: &Snapshot,
+    env_competition_id: Option<i32>
+) -> Result<RaceData> {
     // Determine competition ID
     let competition_id = if let Some(cid) = env_competition_id {
         cid
     } else {
-        get_competition_id(sync_location).context("Failed to get competition ID")?
+        competition_id_from(&snapshot.competitions).context("Failed to get competition ID")?
     };
 
-    println!("Competition ID: {}", competition_id);
+    let competitors = build_competitors(&snapshot.patineurs);
+    let competitors_in_comp = build_competitors_in_competition(&competitors, &snapshot.clubs, &snapshot.regions, &snapshot.patineur_compe, competition_id);
+    let distances = build_distances(&snapshot.distances);
+    let programs = build_programs(&distances, &snapshot.prog_courses, competition_id);
 
-    // Fetch data
-    let mut races = get_races(sync_location, competition_id).context("Failed to get races")?;
-    // Sort races
-    races.sort_by(race_compare);
-    println!("Got {} races", races.len());
+    let mut races = build_races(&programs, &snapshot.vagues, competition_id);
+    // Sort races in running order
+    races.sort_by(running_order_compare);
 
-    let programs = get_programs(sync_location, competition_id).context("Failed to get programs")?;
-    let lanes = get_lanes(sync_location, competition_id).context("Failed to get lanes")?;
-    let competitors = get_competitors(sync_location).context("Failed to get competitors")?;
-    let competitors_in_comp = get_competitors_in_competition(sync_location, competition_id).context("Failed to get competitors in comp")?;
+    let lanes = build_lanes(&races, &competitors_in_comp, &snapshot.pat_vagues);
 
     Ok(RaceData {
         races,
@@ -53,29 +72,241 @@ pub fn fetch_race_data(
     })
 }
 
-pub fn execute_cycle(
-    sync_location: &str, 
-    event_file_path: &str, 
-    json_path: &str, 
+/// `build_race_data`, plus the results from the Lynx results folder of `options`, if set.
+pub fn build_export_data(
+    snapshot: &Snapshot,
+    options: &ExportOptions,
+    env_competition_id: Option<i32>
+) -> Result<RaceData> {
+    let mut race_data = build_race_data(snapshot, env_competition_id)?;
+    if !options.results_folder.is_empty() {
+        let results = load_results(&options.results_folder, &race_data)?;
+        race_data.merge_results(&results);
+    }
+    Ok(race_data)
+}
+
+/// Reads the FinishLynx .LIF files in `results_dir` and matches them to the races.
+pub fn load_results<P: AsRef<Path>>(results_dir: P, race_data: &RaceData) -> Result<RaceResults> {
+    let files = read_lif_dir(results_dir)?;
+    Ok(RaceResults::build(&files, &race_data.races, &race_data.lanes, &race_data.competitors_in_comp))
+}
+
+/// Compares the .LIF results in `results_dir` with the .pat file, without writing anything.
+pub fn plan_write_back<P: AsRef<Path>>(
+    pat_file: &str,
+    options: &ReadOptions,
+    results_dir: P,
+    env_competition_id: Option<i32>
+) -> Result<WriteBackPlan> {
+    let stamp = FileStamp::of(pat_file).ok();
+    let snapshot = open_snapshot(pat_file, options)?;
+    let mut plan = plan_snapshot_write_back(&snapshot, results_dir, env_competition_id)?;
+    plan.source_stamp = stamp;
+    Ok(plan)
+}
+
+/// `plan_write_back` for tables already read. `source_stamp` is left for the caller.
+pub fn plan_snapshot_write_back<P: AsRef<Path>>(
+    snapshot: &Snapshot,
+    results_dir: P,
+    env_competition_id: Option<i32>
+) -> Result<WriteBackPlan> {
+    let race_data = build_race_data(snapshot, env_competition_id)?;
+    let results = load_results(results_dir, &race_data)?;
+
+    Ok(plan_updates(
+        &race_data.races,
+        &race_data.lanes,
+        &race_data.competitors,
+        &race_data.competitors_in_comp,
+        &results
+    ))
+}
+
+/// Backs up the writer's file and writes a previewed plan to it, e.g. through
+/// `OdbcResultWriter` for a .pat file. Returns the backups, one per file written.
+pub fn apply_write_back<W: ResultWriter>(writer: &W, plan: &WriteBackPlan) -> Result<Vec<PathBuf>> {
+    if plan.is_empty() {
+        anyhow::bail!("Nothing to write");
+    }
+    let target = writer.files().into_iter().next().context("Nothing to write to")?;
+    if FileStamp::of(&target).ok() != plan.source_stamp {
+        anyhow::bail!("{} changed since the preview, preview again before writing", target.display());
+    }
+    write_results(writer, &plan.updates)
+}
+
+/// How a .pat file is read for a cycle.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ReadOptions {
+    pub backend: Backend,
+    /// Copy the file to a temp location first and read only from the copy.
+    pub snapshot_copy: bool,
+    /// Leave the copy in the temp folder for debugging instead of deleting it.
+    pub keep_copy: bool,
+}
+
+/// Reads every table from a .pat file, through a consistent copy if requested.
+pub fn open_snapshot(pat_file: &str, options: &ReadOptions) -> Result<Snapshot> {
+    if !options.snapshot_copy {
+        let source = PatSource::open(pat_file, options.backend)?;
+        return Snapshot::load(&source);
+    }
+    let copy = ConsistentCopy::create(pat_file, options.keep_copy)?;
+    let source = PatSource::open(copy.path_str()?, options.backend)?;
+    Snapshot::load(&source)
+}
+
+/// Lists the competitions stored in a .pat file.
+pub fn list_file_competitions(pat_file: &str, options: &ReadOptions) -> Result<Vec<CompetitionInfo>> {
+    let source = PatSource::open(pat_file, options.backend)?;
+    list_competitions(&source)
+}
+
+/// Where a cycle writes its files.
+#[derive(Debug, Clone)]
+pub struct OutputPaths {
+    pub evt: PathBuf,
+    pub json: PathBuf,
+    pub ppl: PathBuf,
+    pub sch: PathBuf,
+}
+
+impl OutputPaths {
+    /// The standard file names inside `folder`.
+    pub fn in_folder<P: AsRef<Path>>(folder: P) -> Self {
+        let folder = folder.as_ref();
+        Self {
+            evt: folder.join("LYNX.EVT"),
+            json: folder.join("races.json"),
+            ppl: folder.join("LYNX.PPL"),
+            sch: folder.join("LYNX.SCH"),
+        }
+    }
+
+    fn all_exist(&self) -> bool {
+        [&self.evt, &self.json, &self.ppl, &self.sch].iter().all(|p| p.exists())
+    }
+}
+
+/// Whether a cycle produced new output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CycleOutcome {
+    Unchanged,
+    Updated,
+}
+
+/// Reads the .pat file unless it is unchanged since the last recorded load.
+/// Returns `None` when neither its mtime/size, the .LIF files in the results
+/// folder (if set), the options, nor the exported tables changed.
+pub fn load_changed_snapshot(
+    pat_file: &str,
+    options: &ReadOptions,
+    export_options: &ExportOptions,
+    env_competition_id: Option<i32>,
+    tracker: &mut ChangeTracker
+) -> Result<Option<Snapshot>> {
+    let results_folder = &export_options.results_folder;
+    let results_stamp = if results_folder.is_empty() { None } else { Some(folder_stamp(results_folder, "lif")?) };
+    // Any option can change the output, so callers don't have to reset the tracker
+    let options_hash = content_hash(&(options, export_options, env_competition_id))?;
+    if !tracker.file_changed(pat_file) && !tracker.results_changed(results_stamp) && !tracker.options_changed(options_hash) {
+        return Ok(None);
+    }
+    // Stamp before reading, so a write during the read is picked up next cycle
+    let stamp = FileStamp::of(pat_file).ok();
+    let snapshot = open_snapshot(pat_file, options)?;
+    tracker.record_file(stamp);
+    tracker.record_results(results_stamp);
+    tracker.record_options(options_hash);
+
+    let hash = content_hash(&(&snapshot, options_hash, results_stamp))?;
+    if !tracker.content_changed(hash) {
+        return Ok(None);
+    }
+    tracker.record_content(hash);
+    Ok(Some(snapshot))
+}
+
+pub fn execute_cycle<S: DataSource>(
+    source: &S,
+    outputs: &OutputPaths,
+    export_options: &ExportOptions,
     env_competition_id: Option<i32>
-) -> Result<()> {
-    let race_data = fetch_race_data(sync_location, env_competition_id)?;
+) -> Result<CycleOutcome> {
+    let snapshot = Snapshot::load(source)?;
+    write_outputs(&snapshot, outputs, export_options, env_competition_id)
+}
+
+/// Runs a cycle against a .pat file, skipping it when nothing changed since the last one.
+pub fn execute_file_cycle(
+    pat_file: &str,
+    options: &ReadOptions,
+    outputs: &OutputPaths,
+    export_options: &ExportOptions,
+    env_competition_id: Option<i32>,
+    tracker: &mut ChangeTracker
+) -> Result<CycleOutcome> {
+    // Outputs removed behind our back must be regenerated
+    if !outputs.all_exist() {
+        tracker.reset();
+    }
 
-    // Clean up old files
-    let _ = fs::remove_file(event_file_path);
-    let _ = fs::remove_file(json_path);
+    let result = load_changed_snapshot(pat_file, options, export_options, env_competition_id, tracker)
+        .and_then(|snapshot| match snapshot {
+            Some(snapshot) => write_outputs(&snapshot, outputs, export_options, env_competition_id),
+            None => Ok(CycleOutcome::Unchanged),
+        });
+    if result.is_err() {
+        tracker.reset();
+    }
+    result
+}
+
+fn write_outputs(
+    snapshot: &Snapshot,
+    outputs: &OutputPaths,
+    export_options: &ExportOptions,
+    env_competition_id: Option<i32>
+) -> Result<CycleOutcome> {
+    let race_data = build_export_data(snapshot, export_options, env_competition_id)?;
 
-    // Write outputs
-    write_lynx_evt(
-        event_file_path,
-        json_path,
+    let evt = render_lynx_evt(
         &race_data.races,
         &race_data.programs,
         &race_data.lanes,
         &race_data.competitors,
-        &race_data.competitors_in_comp
-    ).context("Failed to write output files")?;
+        &race_data.competitors_in_comp,
+        export_options
+    ).context("Failed to generate LYNX.EVT")?;
+    let json = render_race_json(
+        &race_data.races,
+        &race_data.programs,
+        &race_data.lanes,
+        &race_data.competitors,
+        &race_data.competitors_in_comp,
+        export_options
+    ).context("Failed to generate races.json")?;
+    let ppl = render_lynx_ppl(
+        &race_data.competitors,
+        &race_data.competitors_in_comp,
+        export_options
+    ).context("Failed to generate LYNX.PPL")?;
+    // Races are already in running order
+    let sch = render_lynx_sch(&race_data.races).context("Failed to generate LYNX.SCH")?;
+
+    // Only touch files whose content changed, so Lynx doesn't reload needlessly
+    let encoding = export_options.encoding;
+    let evt_written = write_if_changed(&outputs.evt, &encoding.encode(&evt)).context("Failed to write LYNX.EVT")?;
+    let json_written = write_if_changed(&outputs.json, json.as_bytes()).context("Failed to write races.json")?;
+    let ppl_written = write_if_changed(&outputs.ppl, &encoding.encode(&ppl)).context("Failed to write LYNX.PPL")?;
+    let sch_written = write_if_changed(&outputs.sch, &encoding.encode(&sch)).context("Failed to write LYNX.SCH")?;
 
-    println!("Done lynx and json");
-    Ok(())
+    if evt_written || json_written || ppl_written || sch_written {
+        Ok(CycleOutcome::Updated)
+    } else {
+        Ok(CycleOutcome::Unchanged)
+    }
 }
@@ -1,84 +1,131 @@
 use eframe::egui;
-use axum::{
-    extract::State,
-    routing::get,
-    Router,
-    response::{IntoResponse, Json},
-    http::Method,
-};
-use tower_http::cors::CorsLayer;
-use std::sync::{Arc, RwLock};
+use std::collections::HashMap;
 use std::time::{Duration, Instant};
 use tokio::runtime::Runtime;
-use rust_gcpv_lynx_export::app_logic::fetch_race_data;
-use rust_gcpv_lynx_export::writer::generate_race_json;
-use rust_gcpv_lynx_export::writer::JsonRace;
-
-// Shared state for the web server
-type SharedState = Arc<RwLock<Vec<JsonRace>>>;
-
-#[derive(Clone)]
-struct AppState {
-    data: SharedState,
-}
-
-// Web Server Logic
-async fn get_races(State(state): State<AppState>) -> impl IntoResponse {
-    let data = state.data.read().unwrap();
-    Json(data.clone())
-}
-
-async fn run_server(port: u16, state: SharedState) {
-    let app_state = AppState { data: state };
-    
-    let cors = CorsLayer::new()
-        .allow_methods([Method::GET])
-        .allow_origin(tower_http::cors::Any);
-
-    let app = Router::new()
-        .route("/races", get(get_races))
-        .layer(cors)
-        .with_state(app_state);
-
-    let addr = std::net::SocketAddr::from(([0, 0, 0, 0], port));
-    println!("Listening on {}", addr);
-    
-    let listener = tokio::net::TcpListener::bind(addr).await.unwrap();
-    axum::serve(listener, app).await.unwrap();
-}
+use rust_gcpv_lynx_export::app_logic::{load_changed_snapshot, ReadOptions};
+use rust_gcpv_lynx_export::change::ChangeTracker;
+use rust_gcpv_lynx_export::cli::{self, Launch};
+use rust_gcpv_lynx_export::db::Backend;
+use rust_gcpv_lynx_export::gui::{competition_picker, load_competitions, logo_options_ui, name_options_ui, profiles_ui, remember_competition, settings_competition, COMPETITIONS_KEY};
+use rust_gcpv_lynx_export::logic::CompetitionInfo;
+use rust_gcpv_lynx_export::settings::{ExporterSettings, Profiles, PROFILES_KEY, SETTINGS_KEY};
+use rust_gcpv_lynx_export::watcher::{FileWatcher, WatchMode};
+use rust_gcpv_lynx_export::web::{run_server, WebState, LOGO_ROUTE};
+use rust_gcpv_lynx_export::race_time::TimePrecision;
+use rust_gcpv_lynx_export::writer::{ExportOptions, WithdrawnPolicy};
 
 // GUI Application
 struct WebApp {
     pat_file: String,
+    competitions: Vec<CompetitionInfo>,
+    competition_id: Option<i32>,
+    remembered_competitions: HashMap<String, i32>,
     port: u16,
     interval_seconds: u64,
+    read_options: ReadOptions,
+    export_options: ExportOptions,
+    /// Serve the logo folder at /logos and point `affiliationUrl` there
+    serve_logos: bool,
+    watch_mode: WatchMode,
     running: bool,
     status_message: String,
     // State
-    shared_data: SharedState,
+    web_state: WebState,
+    tracker: ChangeTracker,
+    watcher: Option<FileWatcher>,
     last_run: Option<Instant>,
     // Tokio Runtime for server
     runtime: Option<Runtime>,
+    profiles: Profiles,
+    new_profile_name: String,
 }
 
 impl Default for WebApp {
     fn default() -> Self {
+        let settings = ExporterSettings::default();
         Self {
-            pat_file: "".to_owned(),
-            port: 3030,
-            interval_seconds: 60,
+            pat_file: settings.pat_file,
+            competitions: Vec::new(),
+            competition_id: settings.competition_id,
+            remembered_competitions: HashMap::new(),
+            port: settings.port,
+            interval_seconds: settings.interval_seconds,
+            read_options: settings.read_options,
+            export_options: settings.export_options,
+            serve_logos: settings.serve_logos,
+            watch_mode: settings.watch_mode,
             running: false,
             status_message: "Ready".to_owned(),
-            shared_data: Arc::new(RwLock::new(Vec::new())),
+            web_state: WebState::default(),
+            tracker: ChangeTracker::new(),
+            watcher: None,
             last_run: None,
             runtime: None,
+            profiles: Profiles::default(),
+            new_profile_name: "".to_owned(),
         }
     }
 }
 
 impl WebApp {
-    fn new(_cc: &eframe::CreationContext<'_>) -> Self {
-        Self::default()
+    /// `pat_file` (e.g. a file opened from Explorer) replaces the saved one.
+    fn new(cc: &eframe::CreationContext<'_>, pat_file: Option<String>) -> Self {
+        let mut app = Self::default();
+        if let Some(storage) = cc.storage {
+            app.remembered_competitions = eframe::get_value(storage, COMPETITIONS_KEY).unwrap_or_default();
+            app.profiles = eframe::get_value(storage, PROFILES_KEY).unwrap_or_default();
+            if let Some(settings) = eframe::get_value(storage, SETTINGS_KEY) {
+                app.apply_settings(settings);
+            }
+        }
+        if let Some(pat_file) = pat_file {
+            app.pat_file = pat_file;
+            app.refresh_competitions();
+            app.tracker.reset();
+        }
+        app
+    }
+
+    fn settings(&self) -> ExporterSettings {
+        ExporterSettings {
+            pat_file: self.pat_file.clone(),
+            competition_id: self.competition_id,
+            interval_seconds: self.interval_seconds,
+            watch_mode: self.watch_mode,
+            read_options: self.read_options,
+            export_options: self.export_options.clone(),
+            port: self.port,
+            serve_logos: self.serve_logos,
+            ..Default::default()
+        }
+    }
+
+    fn apply_settings(&mut self, settings: ExporterSettings) {
+        self.pat_file = settings.pat_file;
+        self.interval_seconds = settings.interval_seconds;
+        self.watch_mode = settings.watch_mode;
+        self.read_options = settings.read_options;
+        self.export_options = settings.export_options;
+        self.port = settings.port;
+        self.serve_logos = settings.serve_logos;
+        self.refresh_competitions();
+        self.competition_id = settings_competition(&self.competitions, settings.competition_id);
+        remember_competition(&mut self.remembered_competitions, &self.pat_file, self.competition_id);
+        self.tracker.reset();
+    }
+
+    fn refresh_competitions(&mut self) {
+        match load_competitions(&self.pat_file, &self.read_options, &self.remembered_competitions) {
+            Ok((competitions, selected)) => {
+                self.competitions = competitions;
+                self.competition_id = selected;
+            }
+            Err(e) => {
+                self.competitions.clear();
+                self.competition_id = None;
+                self.status_message = format!("Error reading competitions: {}", e);
+            }
+        }
     }
 
     fn start_server(&mut self) {
@@ -89,62 +136,159 @@ impl WebApp {
                 .unwrap();
             
             let port = self.port;
-            let state = self.shared_data.clone();
+            let state = self.web_state.clone();
             
             rt.spawn(async move {
-                run_server(port, state).await;
+                if let Err(e) = run_server(port, state).await {
+                    eprintln!("Web server stopped: {:#}", e);
+                }
             });
             
             self.runtime = Some(rt);
         }
     }
 
+    /// Export options for the served JSON. When serving logos, `affiliationUrl`
+    /// is a /logos path, with the configured folder and fallback served there.
+    fn json_options(&self) -> ExportOptions {
+        let mut options = self.export_options.clone();
+        if self.serve_logos {
+            options.logos = self.web_state.serve_logos(&self.export_options.logos);
+        }
+        options
+    }
+
     fn update_data(&mut self) {
         // Fetch data
-        let env_id = None; 
-        match fetch_race_data(&self.pat_file, env_id) {
-            Ok(race_data) => {
-                // Generate JSON
-                 match generate_race_json(
-                    &race_data.races, 
-                    &race_data.programs, 
-                    &race_data.lanes, 
-                    &race_data.competitors, 
-                    &race_data.competitors_in_comp
-                ) {
-                    Ok(json) => {
-                        let mut data = self.shared_data.write().unwrap();
-                        *data = json;
-                    }
-                    Err(e) => {
-                        eprintln!("Error generating JSON: {:?}", e);
-                        self.status_message = format!("Error generating JSON: {}", e);
-                    }
-                }
+        let env_id = self.competition_id;
+        let now = chrono::Local::now().format("%H:%M:%S");
+        let options = self.json_options();
+        let snapshot = match load_changed_snapshot(&self.pat_file, &self.read_options, &options, env_id, &mut self.tracker) {
+            Ok(Some(snapshot)) => snapshot,
+            Ok(None) => {
+                self.status_message = format!("No changes (checked at {})", now);
+                return;
             }
             Err(e) => {
                  eprintln!("Error fetching data: {:?}", e);
                  self.status_message = format!("Error fetching data: {}", e);
+                 self.tracker.reset();
+                 return;
+            }
+        };
+
+        match self.web_state.publish_snapshot(&snapshot, &options, env_id) {
+            Ok(Some(version)) => self.status_message = format!("Updated at {} (version {})", now, version),
+            Ok(None) => self.status_message = format!("No changes (checked at {})", now),
+            Err(e) => {
+                eprintln!("Error generating JSON: {:?}", e);
+                self.status_message = format!("Error generating JSON: {}", e);
+                self.tracker.reset();
+            }
+        }
+    }
+
+    /// Current race picker with previous/next buttons; also moved by POST /control/current.
+    fn current_race_ui(&mut self, ui: &mut egui::Ui) {
+        let names: Vec<String> = self.web_state.races.read().unwrap().iter().map(|r| r.name.clone()).collect();
+        let cursor = self.web_state.cursor();
+        let mut selected = cursor.current.clone();
+        let mut step = 0;
+
+        ui.horizontal(|ui| {
+            ui.label("Current race:");
+            if ui.button("< Previous").clicked() {
+                step = -1;
             }
+            egui::ComboBox::from_id_salt("current_race")
+                .selected_text(selected.as_deref().unwrap_or("None"))
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(&mut selected, None, "None");
+                    for name in &names {
+                        ui.selectable_value(&mut selected, Some(name.clone()), name);
+                    }
+                });
+            if ui.button("Next >").clicked() {
+                step = 1;
+            }
+        });
+        ui.label(format!("Next: {}   On deck: {}",
+            cursor.next.as_deref().unwrap_or("-"),
+            cursor.on_deck.as_deref().unwrap_or("-")
+        ));
+
+        let result = if step != 0 {
+            Some(self.web_state.step_current(step))
+        } else if selected != cursor.current {
+            Some(self.web_state.set_current(selected.as_deref()))
+        } else {
+            None
+        };
+        if let Some(Err(e)) = result {
+            self.status_message = format!("Error: {}", e);
         }
     }
 }
 
 impl eframe::App for WebApp {
+    fn save(&mut self, storage: &mut dyn eframe::Storage) {
+        eframe::set_value(storage, COMPETITIONS_KEY, &self.remembered_competitions);
+        eframe::set_value(storage, SETTINGS_KEY, &self.settings());
+        eframe::set_value(storage, PROFILES_KEY, &self.profiles);
+    }
+
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
         egui::CentralPanel::default().show(ctx, |ui| {
             ui.heading("GCPV Web Exporter");
             ui.add_space(10.0);
 
+            let current = self.settings();
+            let loaded = ui.add_enabled_ui(!self.running, |ui| {
+                profiles_ui(ui, &mut self.profiles, &mut self.new_profile_name, &current)
+            }).inner;
+            if let Some(settings) = loaded {
+                self.apply_settings(settings);
+            }
+
             // Inputs
             ui.horizontal(|ui| {
                 ui.label("PAT File:");
-                ui.text_edit_singleline(&mut self.pat_file);
+                if ui.text_edit_singleline(&mut self.pat_file).lost_focus() {
+                    self.refresh_competitions();
+                }
                 if ui.button("Select...").clicked() {
                     if let Some(path) = rfd::FileDialog::new().add_filter("PAT", &["pat"]).pick_file() {
                         self.pat_file = path.display().to_string();
+                        self.refresh_competitions();
+                    }
+                }
+            });
+
+            ui.horizontal(|ui| {
+                ui.label("Competition:");
+                if competition_picker(ui, &self.competitions, &mut self.competition_id) {
+                    remember_competition(&mut self.remembered_competitions, &self.pat_file, self.competition_id);
+                    self.tracker.reset();
+                }
+                if ui.button("Refresh").clicked() {
+                    self.refresh_competitions();
+                }
+            });
+
+            ui.horizontal(|ui| {
+                ui.label("Lynx results folder:");
+                let changed = ui.add(egui::TextEdit::singleline(&mut self.export_options.results_folder).hint_text("None"))
+                    .on_hover_text("FinishLynx .LIF files; their results are shown until they are entered in GCPV")
+                    .changed();
+                if ui.button("Select...").clicked() {
+                    if let Some(path) = rfd::FileDialog::new().pick_folder() {
+                        self.export_options.results_folder = path.display().to_string();
+                        self.tracker.reset();
                     }
                 }
+                if changed {
+                    self.tracker.reset();
+                }
             });
 
             ui.horizontal(|ui| {
@@ -153,21 +297,104 @@ impl eframe::App for WebApp {
             });
 
             ui.horizontal(|ui| {
-                ui.label("Interval (s):");
+                ui.label("Fallback interval (s):");
                 ui.add(egui::DragValue::new(&mut self.interval_seconds).range(1..=3600));
             });
 
+            ui.horizontal(|ui| {
+                ui.label("Watch:");
+                egui::ComboBox::from_id_salt("watch_mode")
+                    .selected_text(self.watch_mode.label())
+                    .show_ui(ui, |ui| {
+                        for mode in WatchMode::ALL {
+                            ui.selectable_value(&mut self.watch_mode, mode, mode.label());
+                        }
+                    });
+            });
+
+            ui.horizontal(|ui| {
+                ui.label("Reader:");
+                egui::ComboBox::from_id_salt("backend")
+                    .selected_text(self.read_options.backend.label())
+                    .show_ui(ui, |ui| {
+                        for backend in Backend::ALL {
+                            ui.selectable_value(&mut self.read_options.backend, backend, backend.label());
+                        }
+                    });
+            });
+
+            ui.horizontal(|ui| {
+                ui.checkbox(&mut self.read_options.snapshot_copy, "Read from a copy of the file")
+                    .on_hover_text("Copy the .pat to a temp folder first, so GCPV writes can't be read half-way");
+                if self.read_options.snapshot_copy {
+                    ui.checkbox(&mut self.read_options.keep_copy, "Keep copies for debugging");
+                }
+            });
+
+            ui.horizontal(|ui| {
+                ui.label("Withdrawn skaters:");
+                let before = self.export_options.withdrawn;
+                egui::ComboBox::from_id_salt("withdrawn")
+                    .selected_text(self.export_options.withdrawn.label())
+                    .show_ui(ui, |ui| {
+                        for policy in WithdrawnPolicy::ALL {
+                            ui.selectable_value(&mut self.export_options.withdrawn, policy, policy.label());
+                        }
+                    });
+                if self.export_options.withdrawn != before {
+                    self.tracker.reset();
+                }
+            });
+
+            ui.horizontal(|ui| {
+                ui.label("Time precision:");
+                let before = self.export_options.precision;
+                egui::ComboBox::from_id_salt("precision")
+                    .selected_text(self.export_options.precision.label())
+                    .show_ui(ui, |ui| {
+                        for precision in TimePrecision::ALL {
+                            ui.selectable_value(&mut self.export_options.precision, precision, precision.label());
+                        }
+                    });
+                if self.export_options.precision != before {
+                    self.tracker.reset();
+                }
+            });
+
+            egui::CollapsingHeader::new("Names").show(ui, |ui| {
+                if name_options_ui(ui, &mut self.export_options.names) {
+                    self.tracker.reset();
+                }
+            });
+
+            egui::CollapsingHeader::new("Logos").show(ui, |ui| {
+                if logo_options_ui(ui, &mut self.export_options.logos) {
+                    self.tracker.reset();
+                }
+                if ui.checkbox(&mut self.serve_logos, "Serve logos at /logos")
+                    .on_hover_text("Serve the logo folder over HTTP, so graphics machines can load logos from this PC")
+                    .changed()
+                {
+                    self.tracker.reset();
+                }
+            });
+
             ui.add_space(20.0);
 
             // Controls
             if self.running {
                 if ui.button("Stop").clicked() {
                     self.running = false;
+                    self.watcher = None;
                     self.status_message = "Stopped (Server may still be running)".to_string();
                     // Note: Stopping tokio runtime gracefully in immediate mode GUI is tricky.
                     // For now we just stop updating the data.
                 }
                 ui.label(format!("Running on http://localhost:{}/races", self.port));
+                self.current_race_ui(ui);
+                if self.serve_logos {
+                    ui.label(format!("Logos at http://localhost:{}{}/<name>.png", self.port, LOGO_ROUTE));
+                }
             } else {
                 if ui.button("Start").clicked() {
                      if self.pat_file.is_empty() {
@@ -175,6 +402,14 @@ impl eframe::App for WebApp {
                     } else {
                         self.running = true;
                         self.status_message = "Starting...".to_string();
+                        self.tracker.reset();
+                        self.watcher = match FileWatcher::new(&self.pat_file, self.watch_mode) {
+                            Ok(watcher) => Some(watcher),
+                            Err(e) => {
+                                eprintln!("Could not watch file: {:?}", e);
+                                None
+                            }
+                        };
                         self.start_server();
                         self.update_data(); // Initial update
                         self.last_run = Some(Instant::now());
@@ -183,12 +418,19 @@ impl eframe::App for WebApp {
             }
             
             ui.label(&self.status_message);
+            if self.running {
+                ui.label(match &self.watcher {
+                    Some(w) => format!("Watching file ({}), checking every {}s as fallback", w.mode().label(), self.interval_seconds),
+                    None => format!("File watching unavailable, checking every {}s", self.interval_seconds),
+                });
+            }
 
             // Loop
             if self.running {
                 let now = Instant::now();
+                let changed = self.watcher.as_ref().map(|w| w.poll_changed()).unwrap_or(false);
                  if let Some(last) = self.last_run {
-                    if now.duration_since(last) >= Duration::from_secs(self.interval_seconds) {
+                    if changed || now.duration_since(last) >= Duration::from_secs(self.interval_seconds) {
                         self.update_data();
                         self.last_run = Some(now);
                     }
@@ -200,8 +442,11 @@ impl eframe::App for WebApp {
 }
 
 fn main() -> eframe::Result<()> {
-    // Need to initialize generic array or similar? No, standard Vec.
-    
+    let pat_file = match cli::launch_mode(std::env::args_os().skip(1)) {
+        Launch::Cli => std::process::exit(cli::main()),
+        Launch::Gui { pat_file } => pat_file,
+    };
+
     let native_options = eframe::NativeOptions {
         viewport: egui::ViewportBuilder::default()
             .with_inner_size([500.0, 300.0]),
@@ -211,6 +456,6 @@ fn main() -> eframe::Result<()> {
     eframe::run_native(
         "GCPV Web Export",
         native_options,
-        Box::new(|cc| Ok(Box::new(WebApp::new(cc)))),
+        Box::new(|cc| Ok(Box::new(WebApp::new(cc, pat_file)))),
     )
 }
@@ -0,0 +1,313 @@
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::io::Write;
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+
+/// Modification time and size of a file, used as a cheap first-pass change check.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileStamp {
+    modified: Option<SystemTime>,
+    len: u64,
+}
+
+impl FileStamp {
+    pub fn of<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let meta = std::fs::metadata(path)?;
+        Ok(Self {
+            modified: meta.modified().ok(),
+            len: meta.len(),
+        })
+    }
+
+    pub fn size(&self) -> u64 {
+        self.len
+    }
+}
+
+/// Names, mtimes and sizes of the files in `dir` with the given extension,
+/// hashed, so files added, removed or rewritten change the stamp.
+pub fn folder_stamp<P: AsRef<Path>>(dir: P, extension: &str) -> Result<u64> {
+    let dir = dir.as_ref();
+    let mut files = Vec::new();
+    for entry in std::fs::read_dir(dir).with_context(|| format!("Failed to read {}", dir.display()))? {
+        let path = entry?.path();
+        if path.extension().and_then(|e| e.to_str()).is_some_and(|e| e.eq_ignore_ascii_case(extension)) {
+            let stamp = FileStamp::of(&path).ok();
+            files.push((path, stamp.as_ref().and_then(|s| s.modified), stamp.map(|s| s.len)));
+        }
+    }
+    files.sort();
+    content_hash(&files)
+}
+
+/// Hash of any serializable value, e.g. the tables read for a cycle.
+pub fn content_hash<T: Serialize>(value: &T) -> Result<u64> {
+    let bytes = serde_json::to_vec(value)?;
+    let mut hasher = DefaultHasher::new();
+    bytes.hash(&mut hasher);
+    Ok(hasher.finish())
+}
+
+/// Remembers what the last successful cycle was built from, so unchanged
+/// inputs can skip the export entirely.
+#[derive(Debug, Default)]
+pub struct ChangeTracker {
+    file_stamp: Option<FileStamp>,
+    /// `folder_stamp` of the Lynx results folder, when one is used
+    results_stamp: Option<u64>,
+    /// `content_hash` of the read and export options
+    options_hash: Option<u64>,
+    content_hash: Option<u64>,
+}
+
+impl ChangeTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Forget everything, forcing the next cycle to run (e.g. after settings change).
+    pub fn reset(&mut self) {
+        self.file_stamp = None;
+        self.results_stamp = None;
+        self.options_hash = None;
+        self.content_hash = None;
+    }
+
+    /// True unless the file has the same mtime and size as the last recorded cycle.
+    pub fn file_changed<P: AsRef<Path>>(&self, path: P) -> bool {
+        match (&self.file_stamp, FileStamp::of(path)) {
+            (Some(last), Ok(current)) => *last != current,
+            _ => true,
+        }
+    }
+
+    pub fn results_changed(&self, stamp: Option<u64>) -> bool {
+        self.results_stamp != stamp
+    }
+
+    pub fn options_changed(&self, hash: u64) -> bool {
+        self.options_hash != Some(hash)
+    }
+
+    pub fn content_changed(&self, hash: u64) -> bool {
+        self.content_hash != Some(hash)
+    }
+
+    pub fn record_file(&mut self, stamp: Option<FileStamp>) {
+        self.file_stamp = stamp;
+    }
+
+    pub fn record_results(&mut self, stamp: Option<u64>) {
+        self.results_stamp = stamp;
+    }
+
+    pub fn record_options(&mut self, hash: u64) {
+        self.options_hash = Some(hash);
+    }
+
+    pub fn record_content(&mut self, hash: u64) {
+        self.content_hash = Some(hash);
+    }
+}
+
+/// Writes `contents` to `path` unless the file already holds exactly that.
+/// Returns whether the file was written.
+pub fn write_if_changed<P: AsRef<Path>>(path: P, contents: &[u8]) -> Result<bool> {
+    let path = path.as_ref();
+    if let Ok(existing) = std::fs::read(path) {
+        if existing == contents {
+            return Ok(false);
+        }
+    }
+    write_atomic(path, contents)?;
+    Ok(true)
+}
+
+/// Writes `contents` to a temp file next to `path`, then renames it over
+/// `path`, so readers see either the old file or the new one, never a
+/// missing or half-written file. On failure the old file is left as it was.
+pub fn write_atomic<P: AsRef<Path>>(path: P, contents: &[u8]) -> Result<()> {
+    let path = path.as_ref();
+    let file_name = path.file_name()
+        .and_then(|n| n.to_str())
+        .with_context(|| format!("{} is not a file path", path.display()))?;
+    // Same directory, so the rename doesn't cross file systems
+    let temp = path.with_file_name(format!(".{}.{}.tmp", file_name, std::process::id()));
+
+    let result = write_and_rename(&temp, path, contents);
+    if result.is_err() {
+        let _ = std::fs::remove_file(&temp);
+    }
+    result.with_context(|| format!("Failed to write {}", path.display()))
+}
+
+fn write_and_rename(temp: &Path, path: &Path, contents: &[u8]) -> Result<()> {
+    let mut file = std::fs::File::create(temp)?;
+    file.write_all(contents)?;
+    file.sync_all()?;
+    drop(file);
+
+    // On Windows the rename fails while a reader has the file open; retry briefly
+    let mut attempts = 0;
+    loop {
+        match std::fs::rename(temp, path) {
+            Ok(()) => return Ok(()),
+            Err(_) if attempts < RENAME_RETRIES => {
+                attempts += 1;
+                std::thread::sleep(Duration::from_millis(100));
+            }
+            Err(e) => return Err(e.into()),
+        }
+    }
+}
+
+const RENAME_RETRIES: u32 = 5;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("gcpv-change-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn tracks_file_stamps() {
+        let dir = temp_dir("stamps");
+        let file = dir.join("comp.pat");
+        std::fs::write(&file, "first").unwrap();
+
+        let mut tracker = ChangeTracker::new();
+        assert!(tracker.file_changed(&file));
+        tracker.record_file(FileStamp::of(&file).ok());
+        assert!(!tracker.file_changed(&file));
+
+        std::fs::write(&file, "second, longer").unwrap();
+        assert!(tracker.file_changed(&file));
+        tracker.record_file(FileStamp::of(&file).ok());
+        assert!(!tracker.file_changed(&file));
+
+        // A missing file always counts as changed, so the cycle reports the error
+        std::fs::remove_file(&file).unwrap();
+        assert!(tracker.file_changed(&file));
+
+        tracker.reset();
+        std::fs::write(&file, "second, longer").unwrap();
+        assert!(tracker.file_changed(&file));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn tracks_content_and_results() {
+        let mut tracker = ChangeTracker::new();
+        let hash = content_hash(&("TPatVagues", 1)).unwrap();
+        assert_eq!(hash, content_hash(&("TPatVagues", 1)).unwrap());
+        assert_ne!(hash, content_hash(&("TPatVagues", 2)).unwrap());
+
+        assert!(tracker.content_changed(hash));
+        tracker.record_content(hash);
+        assert!(!tracker.content_changed(hash));
+        assert!(tracker.content_changed(hash + 1));
+
+        // No results folder on either side
+        assert!(!tracker.results_changed(None));
+        assert!(tracker.results_changed(Some(1)));
+        tracker.record_results(Some(1));
+        assert!(!tracker.results_changed(Some(1)));
+
+        tracker.reset();
+        assert!(tracker.content_changed(hash));
+        assert!(!tracker.results_changed(None));
+    }
+
+    #[test]
+    fn tracks_options() {
+        use crate::race_time::TimePrecision;
+        use crate::writer::ExportOptions;
+
+        let mut tracker = ChangeTracker::new();
+        let options = ExportOptions::default();
+        let hash = content_hash(&options).unwrap();
+        assert!(tracker.options_changed(hash));
+        tracker.record_options(hash);
+        assert!(!tracker.options_changed(content_hash(&options.clone()).unwrap()));
+
+        let precise = ExportOptions { precision: TimePrecision::Thousandths, ..options };
+        assert!(tracker.options_changed(content_hash(&precise).unwrap()));
+
+        tracker.reset();
+        assert!(tracker.options_changed(hash));
+    }
+
+    #[test]
+    fn stamps_folders_by_extension() {
+        let dir = temp_dir("folder");
+        let empty = folder_stamp(&dir, "lif").unwrap();
+        std::fs::write(dir.join("notes.txt"), "ignored").unwrap();
+        assert_eq!(folder_stamp(&dir, "lif").unwrap(), empty);
+
+        std::fs::write(dir.join("001-1-01.lif"), "1,1,1,").unwrap();
+        let one = folder_stamp(&dir, "lif").unwrap();
+        assert_ne!(one, empty);
+        assert_eq!(folder_stamp(&dir, "LIF").unwrap(), one);
+
+        std::fs::write(dir.join("001-1-01.lif"), "1,1,1,Heat 1").unwrap();
+        assert_ne!(folder_stamp(&dir, "lif").unwrap(), one);
+
+        assert!(folder_stamp(dir.join("missing"), "lif").is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    fn leftover_temp_files(dir: &Path) -> Vec<PathBuf> {
+        std::fs::read_dir(dir).unwrap()
+            .map(|e| e.unwrap().path())
+            .filter(|p| p.extension().is_some_and(|e| e == "tmp"))
+            .collect()
+    }
+
+    #[test]
+    fn writes_only_changed_content() {
+        let dir = temp_dir("if-changed");
+        let file = dir.join("LYNX.EVT");
+        assert!(write_if_changed(&file, b"1A,1,01").unwrap());
+        assert!(!write_if_changed(&file, b"1A,1,01").unwrap());
+        assert!(write_if_changed(&file, b"1A,1,01,Heat").unwrap());
+        assert_eq!(std::fs::read(&file).unwrap(), b"1A,1,01,Heat");
+        assert!(leftover_temp_files(&dir).is_empty());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn replaces_files_atomically() {
+        let dir = temp_dir("atomic");
+        let file = dir.join("races.json");
+        std::fs::write(&file, "old").unwrap();
+        write_atomic(&file, b"new").unwrap();
+        assert_eq!(std::fs::read_to_string(&file).unwrap(), "new");
+        assert!(leftover_temp_files(&dir).is_empty());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn failed_write_keeps_the_old_file() {
+        let dir = temp_dir("atomic-fail");
+        // A folder can't be replaced by a file, so the rename fails
+        let target = dir.join("races.json");
+        std::fs::create_dir(&target).unwrap();
+        std::fs::write(target.join("keep"), "old").unwrap();
+        assert!(write_atomic(&target, b"new").is_err());
+        assert_eq!(std::fs::read_to_string(target.join("keep")).unwrap(), "old");
+        assert!(leftover_temp_files(&dir).is_empty());
+
+        assert!(write_atomic(dir.join("missing").join("races.json"), b"new").is_err());
+        assert!(write_atomic(Path::new("/"), b"new").is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
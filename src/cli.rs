@@ -0,0 +1,414 @@
+use crate::app_logic::{build_export_data, execute_file_cycle, list_file_competitions, load_changed_snapshot, open_snapshot, CycleOutcome, OutputPaths, ReadOptions};
+use crate::change::ChangeTracker;
+use crate::db::Backend;
+use crate::logos::{LogoKey, LogoOptions};
+use crate::race_time::TimePrecision;
+use crate::text::{NameOptions, OutputEncoding};
+use crate::watcher::{FileWatcher, WatchMode};
+use crate::web::{run_server, WebState};
+use crate::writer::{generate_race_json, ExportOptions, RaceStatus, WithdrawnPolicy};
+use anyhow::{Context, Result};
+use clap::{Args, CommandFactory, Parser, Subcommand};
+use std::ffi::OsString;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
+
+/// Headless mode: every GUI setting is a flag, and every flag can also be
+/// set through the environment variable shown in `--help` (or a `.env` file).
+#[derive(Debug, Parser)]
+#[command(name = "gcpv-lynx-export", version, about = "Exports GCPV .pat competitions to FinishLynx files and a JSON web API")]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Write LYNX.EVT, LYNX.SCH, LYNX.PPL and races.json once
+    Export {
+        #[command(flatten)]
+        source: SourceArgs,
+        #[command(flatten)]
+        output: OutputArgs,
+        #[command(flatten)]
+        export: ExportArgs,
+    },
+    /// Write the files again whenever the .pat file changes
+    Watch {
+        #[command(flatten)]
+        source: SourceArgs,
+        #[command(flatten)]
+        output: OutputArgs,
+        #[command(flatten)]
+        export: ExportArgs,
+        #[command(flatten)]
+        watch: WatchArgs,
+    },
+    /// Serve the races over HTTP, as web_export does, updated when the .pat file changes
+    Serve {
+        #[command(flatten)]
+        source: SourceArgs,
+        #[command(flatten)]
+        export: ExportArgs,
+        #[command(flatten)]
+        watch: WatchArgs,
+        #[command(flatten)]
+        server: ServerArgs,
+    },
+    /// List the competitions in a .pat file, and optionally the races of one
+    Inspect {
+        #[command(flatten)]
+        source: SourceArgs,
+        #[command(flatten)]
+        export: ExportArgs,
+        /// Also list the races, in running order
+        #[arg(long)]
+        races: bool,
+    },
+}
+
+#[derive(Debug, Args)]
+pub struct SourceArgs {
+    /// GCPV competition file
+    #[arg(long, env = "GCPV_PAT")]
+    pub pat: String,
+    /// Competition id; defaults to the only competition in the file
+    #[arg(long, env = "GCPV_COMPETITION")]
+    pub competition: Option<i32>,
+    /// How the .pat file is read: jet, mdb-export or odbc
+    #[arg(long, env = "DB_BACKEND", default_value_t, value_parser = Backend::from_str)]
+    pub backend: Backend,
+    /// Copy the .pat to a temp folder first, so GCPV writes can't be read half-way
+    #[arg(long, env = "GCPV_SNAPSHOT_COPY")]
+    pub snapshot_copy: bool,
+    /// Keep those copies for debugging
+    #[arg(long, env = "GCPV_KEEP_COPY")]
+    pub keep_copy: bool,
+}
+
+impl SourceArgs {
+    fn read_options(&self) -> ReadOptions {
+        ReadOptions {
+            backend: self.backend,
+            snapshot_copy: self.snapshot_copy,
+            keep_copy: self.keep_copy,
+        }
+    }
+}
+
+#[derive(Debug, Args)]
+pub struct OutputArgs {
+    /// Folder for LYNX.EVT, LYNX.SCH, LYNX.PPL and races.json
+    #[arg(long, short, env = "GCPV_OUTPUT", default_value = ".")]
+    pub output: PathBuf,
+}
+
+#[derive(Debug, Args)]
+pub struct ExportArgs {
+    /// Withdrawn skaters: omit, flag or include
+    #[arg(long, env = "GCPV_WITHDRAWN", default_value = "omit")]
+    pub withdrawn: WithdrawnPolicy,
+    /// Lynx file encoding: utf-8, windows-1252 or ascii
+    #[arg(long, env = "GCPV_ENCODING", default_value = "utf-8")]
+    pub encoding: OutputEncoding,
+    /// Decimals of times in races.json: seconds, tenths, hundredths or thousandths
+    #[arg(long, env = "GCPV_PRECISION", default_value = "hundredths")]
+    pub precision: TimePrecision,
+    /// Write last names in capitals
+    #[arg(long, env = "GCPV_UPPERCASE_LAST_NAMES")]
+    pub uppercase_last_names: bool,
+    /// Leave commas in names
+    #[arg(long, env = "GCPV_KEEP_COMMAS")]
+    pub keep_commas: bool,
+    /// Leave extra whitespace in names
+    #[arg(long, env = "GCPV_KEEP_SPACES")]
+    pub keep_spaces: bool,
+    /// Logo folder or URL template (see README)
+    #[arg(long, env = "LOGO_BASE", default_value = "")]
+    pub logo_base: String,
+    /// What names logo files: affiliation, club or region
+    #[arg(long, env = "LOGO_KEY", default_value = "affiliation")]
+    pub logo_key: LogoKey,
+    /// Logo used when a skater has no club, or a local logo file is missing
+    #[arg(long, env = "LOGO_FALLBACK", default_value = "")]
+    pub logo_fallback: String,
+    /// Folder of FinishLynx .LIF files; their results fill in lanes GCPV has none for
+    #[arg(long, env = "GCPV_LYNX_RESULTS", default_value = "")]
+    pub lynx_results: String,
+}
+
+impl ExportArgs {
+    fn export_options(&self) -> ExportOptions {
+        ExportOptions {
+            withdrawn: self.withdrawn,
+            logos: LogoOptions {
+                base: self.logo_base.clone(),
+                key: self.logo_key,
+                fallback: self.logo_fallback.clone(),
+            },
+            names: NameOptions {
+                uppercase_last_names: self.uppercase_last_names,
+                strip_commas: !self.keep_commas,
+                trim: !self.keep_spaces,
+            },
+            encoding: self.encoding,
+            precision: self.precision,
+            results_folder: self.lynx_results.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Args)]
+pub struct WatchArgs {
+    /// Seconds between checks when no file change is reported
+    #[arg(long, env = "GCPV_INTERVAL", default_value = "60")]
+    pub interval: u64,
+    /// How changes are detected: auto, native or poll
+    #[arg(long, env = "GCPV_WATCH_MODE", default_value = "auto")]
+    pub watch_mode: WatchMode,
+}
+
+impl WatchArgs {
+    fn watcher(&self, pat_file: &str) -> Option<FileWatcher> {
+        match FileWatcher::new(pat_file, self.watch_mode) {
+            Ok(watcher) => {
+                println!("Watching {} ({}), checking every {}s as fallback", pat_file, watcher.mode().label(), self.interval);
+                Some(watcher)
+            }
+            Err(e) => {
+                eprintln!("Could not watch file, checking every {}s: {:#}", self.interval, e);
+                None
+            }
+        }
+    }
+
+    /// Waits for the next change, or the interval.
+    fn wait(&self, watcher: Option<&FileWatcher>) {
+        let interval = Duration::from_secs(self.interval.max(1));
+        match watcher {
+            Some(watcher) => {
+                watcher.wait_changed(interval);
+            }
+            None => std::thread::sleep(interval),
+        }
+    }
+}
+
+#[derive(Debug, Args)]
+pub struct ServerArgs {
+    /// Port the web API listens on, on every network interface
+    #[arg(long, env = "GCPV_PORT", default_value = "3030")]
+    pub port: u16,
+    /// Serve the logo folder at /logos and point affiliationUrl there
+    #[arg(long, env = "SERVE_LOGOS")]
+    pub serve_logos: bool,
+}
+
+/// How an executable was started.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Launch {
+    Cli,
+    /// The window, with the file it was opened with, if any
+    Gui { pat_file: Option<String> },
+}
+
+/// Only a subcommand, `help`, or `--help`/`--version` starts the command line.
+/// Anything else opens the window: a .pat file opened from Explorer or
+/// dropped on the exe arrives as the first argument.
+pub fn launch_mode<I, T>(args: I) -> Launch
+where
+    I: IntoIterator<Item = T>,
+    T: Into<OsString>,
+{
+    let Some(first) = args.into_iter().next().map(Into::into) else {
+        return Launch::Gui { pat_file: None };
+    };
+    let first = first.to_string_lossy().into_owned();
+    let command = Cli::command();
+    let is_subcommand = first == "help" || command.get_subcommands().any(|c| c.get_name() == first);
+    if is_subcommand || matches!(first.as_str(), "-h" | "--help" | "-V" | "--version") {
+        Launch::Cli
+    } else {
+        Launch::Gui { pat_file: Some(first) }
+    }
+}
+
+/// Parses the command line and runs it. Returns the process exit code.
+pub fn main() -> i32 {
+    dotenvy::dotenv().ok();
+    match run(Cli::parse()) {
+        Ok(()) => 0,
+        Err(e) => {
+            eprintln!("Error: {:#}", e);
+            1
+        }
+    }
+}
+
+pub fn run(cli: Cli) -> Result<()> {
+    match cli.command {
+        Command::Export { source, output, export } => {
+            let outputs = OutputPaths::in_folder(&output.output);
+            let mut tracker = ChangeTracker::new();
+            execute_file_cycle(&source.pat, &source.read_options(), &outputs, &export.export_options(), source.competition, &mut tracker)?;
+            println!("Wrote {}", output.output.display());
+            Ok(())
+        }
+        Command::Watch { source, output, export, watch } => {
+            let outputs = OutputPaths::in_folder(&output.output);
+            let (read_options, export_options) = (source.read_options(), export.export_options());
+            let watcher = watch.watcher(&source.pat);
+            let mut tracker = ChangeTracker::new();
+            loop {
+                let now = chrono::Local::now().format("%H:%M:%S");
+                match execute_file_cycle(&source.pat, &read_options, &outputs, &export_options, source.competition, &mut tracker) {
+                    Ok(CycleOutcome::Updated) => println!("Updated at {}", now),
+                    Ok(CycleOutcome::Unchanged) => {}
+                    Err(e) => eprintln!("Export failed at {}: {:#}", now, e),
+                }
+                watch.wait(watcher.as_ref());
+            }
+        }
+        Command::Serve { source, export, watch, server } => serve(source, export, watch, server),
+        Command::Inspect { source, export, races } => inspect(&source, &export, races),
+    }
+}
+
+fn serve(source: SourceArgs, export: ExportArgs, watch: WatchArgs, server: ServerArgs) -> Result<()> {
+    let state = WebState::new();
+    let read_options = source.read_options();
+    let mut export_options = export.export_options();
+    if server.serve_logos {
+        export_options.logos = state.serve_logos(&export_options.logos);
+    }
+
+    let runtime = tokio::runtime::Runtime::new().context("Failed to start the web server runtime")?;
+    let server_task = runtime.spawn(run_server(server.port, state.clone()));
+    println!("Serving http://localhost:{}/races", server.port);
+
+    let watcher = watch.watcher(&source.pat);
+    let mut tracker = ChangeTracker::new();
+    loop {
+        if server_task.is_finished() {
+            return runtime.block_on(server_task)?;
+        }
+
+        let now = chrono::Local::now().format("%H:%M:%S");
+        let published = load_changed_snapshot(&source.pat, &read_options, &export_options, source.competition, &mut tracker)
+            .and_then(|snapshot| match snapshot {
+                Some(snapshot) => state.publish_snapshot(&snapshot, &export_options, source.competition),
+                None => Ok(None),
+            });
+        match published {
+            Ok(Some(version)) => println!("Updated at {} (version {})", now, version),
+            Ok(None) => {}
+            Err(e) => {
+                eprintln!("Update failed at {}: {:#}", now, e);
+                tracker.reset();
+            }
+        }
+        watch.wait(watcher.as_ref());
+    }
+}
+
+fn inspect(source: &SourceArgs, export: &ExportArgs, list_races: bool) -> Result<()> {
+    let read_options = source.read_options();
+    let competitions = list_file_competitions(&source.pat, &read_options)?;
+    println!("{}: {} competition(s)", source.pat, competitions.len());
+    for competition in &competitions {
+        println!("  {}", competition.label());
+    }
+    if !list_races {
+        return Ok(());
+    }
+
+    // Same options as an export, so withdrawn skaters and Lynx results count the same way
+    let export_options = export.export_options();
+    let snapshot = open_snapshot(&source.pat, &read_options)?;
+    let race_data = build_export_data(&snapshot, &export_options, source.competition)?;
+    let races = generate_race_json(
+        &race_data.races,
+        &race_data.programs,
+        &race_data.lanes,
+        &race_data.competitors,
+        &race_data.competitors_in_comp,
+        &export_options
+    )?;
+    println!("{} races, {} skaters", races.len(), race_data.competitors_in_comp.len());
+    for race in &races {
+        println!("  {:<6} {:<14} {:<28} {:>5}m  {} lanes  {}",
+            race.name,
+            race.round.label(),
+            race.group.as_deref().unwrap_or(""),
+            race.length.unwrap_or(0),
+            race.lanes.len(),
+            match race.status {
+                RaceStatus::Scheduled => "scheduled",
+                RaceStatus::Results => "results",
+            }
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn starts_the_command_line_only_when_asked() {
+        let cli = ["export", "watch", "serve", "inspect", "help", "-h", "--help", "-V", "--version"];
+        for arg in cli {
+            assert_eq!(launch_mode([arg]), Launch::Cli, "{}", arg);
+        }
+        let window = [r"C:\GCPV\BC Cup.pat", "comp.pat", "Export", "--pat"];
+        for arg in window {
+            assert_eq!(launch_mode([arg]), Launch::Gui { pat_file: Some(arg.to_string()) }, "{}", arg);
+        }
+        assert_eq!(launch_mode(Vec::<String>::new()), Launch::Gui { pat_file: None });
+    }
+
+    #[test]
+    fn parses_every_command() {
+        Cli::command().debug_assert();
+        let cli = Cli::try_parse_from([
+            "gcpv-lynx-export", "inspect", "--pat", "comp.pat", "--races",
+            "--withdrawn", "flag", "--uppercase-last-names", "--precision", "tenths",
+        ]).unwrap();
+        let Command::Inspect { source, export, races } = cli.command else {
+            panic!("not inspect");
+        };
+        assert_eq!(source.pat, "comp.pat");
+        assert!(races);
+        let options = export.export_options();
+        assert_eq!(options.withdrawn, WithdrawnPolicy::Flag);
+        assert!(options.names.uppercase_last_names);
+        assert_eq!(options.precision, TimePrecision::Tenths);
+    }
+
+    #[test]
+    fn reads_the_backend_like_the_windows() {
+        let inspect = |args: &[&str]| {
+            let cli = Cli::try_parse_from(["gcpv-lynx-export", "inspect", "--pat", "comp.pat"].iter().chain(args));
+            cli.map(|cli| match cli.command {
+                Command::Inspect { source, .. } => source.backend,
+                _ => panic!("not inspect"),
+            })
+        };
+        // The only test that sets DB_BACKEND
+        std::env::remove_var("DB_BACKEND");
+        assert_eq!(inspect(&[]).unwrap(), Backend::Jet);
+        assert_eq!(inspect(&["--backend", "mdbtools"]).unwrap(), Backend::MdbExport);
+        assert!(inspect(&["--backend", "access"]).is_err());
+
+        std::env::set_var("DB_BACKEND", "mdb-export");
+        let (cli, window) = (inspect(&[]), Backend::from_env());
+        std::env::remove_var("DB_BACKEND");
+        assert_eq!(cli.unwrap(), Backend::MdbExport);
+        assert_eq!(window, Backend::MdbExport);
+        for backend in Backend::ALL {
+            assert_eq!(backend.to_string().parse::<Backend>().unwrap(), backend);
+        }
+    }
+}
@@ -1,8 +1,9 @@
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use anyhow::{Context, Result};
 use std::process::Command;
+use crate::jet::JetFile;
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct TCompetition {
     #[serde(rename = "NoCompetition")]
     pub no_competition: Option<i32>,
@@ -14,7 +15,7 @@ pub struct TCompetition {
     pub no_club: Option<i32>,
 }
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct TPatineurs {
     #[serde(rename = "NoPatineur")]
     pub no_patineur: Option<i32>,
@@ -36,7 +37,7 @@ pub struct TPatineurs {
     pub code_pat: Option<String>,
 }
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct TPatineurCompe {
     #[serde(rename = "NoPatCompe")]
     pub no_pat_compe: Option<i32>,
@@ -58,7 +59,7 @@ pub struct TPatineurCompe {
     pub no_casque: Option<i32>,
 }
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct TClubs {
     #[serde(rename = "NoClub")]
     pub no_club: Option<i32>,
@@ -72,7 +73,15 @@ pub struct TClubs {
     pub abreviation: Option<String>,
 }
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TRegions {
+    #[serde(rename = "NoRegion")]
+    pub no_region: Option<i32>,
+    #[serde(rename = "Region")]
+    pub region: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct TDistancesStandards {
     #[serde(rename = "NoDistance")]
     pub no_distance: Option<i32>,
@@ -82,7 +91,7 @@ pub struct TDistancesStandards {
     pub longueur_epreuve: Option<i32>,
 }
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct TProgCourses {
     #[serde(rename = "CleDistancesCompe")]
     pub cle_distances_compe: Option<i32>,
@@ -100,7 +109,7 @@ pub struct TProgCourses {
     pub ordre_sequence: Option<f64>,
 }
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct TVagues {
     #[serde(rename = "CleTVagues")]
     pub cle_tvagues: Option<i32>,
@@ -114,13 +123,14 @@ pub struct TVagues {
     pub seq: Option<i32>,
 }
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct TPatVagues {
     #[serde(rename = "CleTVagues")]
     pub cle_tvagues: Option<i32>,
     #[serde(rename = "NoPatCompe")]
     pub no_pat_compe: Option<i32>,
-    #[serde(rename = "Temps")]
+    /// Text, as mdb-export prints the Double; JSON fixtures can also give a number
+    #[serde(rename = "Temps", default, deserialize_with = "text_or_number")]
     pub temps: Option<String>,
     #[serde(rename = "Rang")]
     pub rang: Option<i32>,
@@ -130,27 +140,207 @@ pub struct TPatVagues {
     pub cle_tpat_vagues: Option<i32>,
 }
 
-fn get_mdb_export_command() -> String {
+/// Reads a column as text whether it holds a string or a number.
+fn text_or_number<'de, D: serde::Deserializer<'de>>(deserializer: D) -> std::result::Result<Option<String>, D::Error> {
+    struct TextOrNumber;
+
+    impl<'de> serde::de::Visitor<'de> for TextOrNumber {
+        type Value = Option<String>;
+
+        fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+            f.write_str("a string, a number or null")
+        }
+
+        fn visit_none<E: serde::de::Error>(self) -> std::result::Result<Self::Value, E> {
+            Ok(None)
+        }
+
+        fn visit_unit<E: serde::de::Error>(self) -> std::result::Result<Self::Value, E> {
+            Ok(None)
+        }
+
+        fn visit_some<D: serde::Deserializer<'de>>(self, deserializer: D) -> std::result::Result<Self::Value, D::Error> {
+            deserializer.deserialize_any(self)
+        }
+
+        fn visit_str<E: serde::de::Error>(self, v: &str) -> std::result::Result<Self::Value, E> {
+            Ok(Some(v.to_string()))
+        }
+
+        fn visit_i64<E: serde::de::Error>(self, v: i64) -> std::result::Result<Self::Value, E> {
+            Ok(Some(v.to_string()))
+        }
+
+        fn visit_u64<E: serde::de::Error>(self, v: u64) -> std::result::Result<Self::Value, E> {
+            Ok(Some(v.to_string()))
+        }
+
+        fn visit_f64<E: serde::de::Error>(self, v: f64) -> std::result::Result<Self::Value, E> {
+            Ok(Some(v.to_string()))
+        }
+    }
+
+    deserializer.deserialize_option(TextOrNumber)
+}
+
+fn get_mdbtools_command(tool: &str) -> String {
     if cfg!(target_os = "windows") {
         if let Ok(exe_path) = std::env::current_exe() {
             if let Some(dir) = exe_path.parent() {
-                let local_mdb = dir.join("mdb-export.exe");
-                if local_mdb.exists() {
-                    return local_mdb.to_string_lossy().to_string();
+                let local_tool = dir.join(format!("{}.exe", tool));
+                if local_tool.exists() {
+                    return local_tool.to_string_lossy().to_string();
                 }
             }
         }
     }
-    "mdb-export".to_string()
+    tool.to_string()
+}
+
+fn get_mdb_export_command() -> String {
+    get_mdbtools_command("mdb-export")
+}
+
+fn get_mdb_tables_command() -> String {
+    get_mdbtools_command("mdb-tables")
+}
+
+/// How tables are read out of a .pat file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum Backend {
+    /// Built-in Jet3/Jet4 page reader, no external tooling needed.
+    #[default]
+    Jet,
+    /// The `mdb-export` tool from mdbtools.
+    MdbExport,
+    /// Microsoft Access ODBC driver (Windows only).
+    Odbc,
+}
+
+impl Backend {
+    pub const ALL: [Backend; 3] = [Backend::Jet, Backend::MdbExport, Backend::Odbc];
+
+    /// Backend selected by the `DB_BACKEND` environment variable, defaulting to `Jet`.
+    /// Parsed by `FromStr`, as the command line's `--backend` is.
+    pub fn from_env() -> Self {
+        match std::env::var("DB_BACKEND").ok().map(|v| v.parse()) {
+            Some(Ok(backend)) => backend,
+            Some(Err(e)) => {
+                eprintln!("Ignoring DB_BACKEND: {:#}", e);
+                Backend::default()
+            }
+            None => Backend::default(),
+        }
+    }
+
+    /// The name `FromStr` reads and `--backend` shows.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Backend::Jet => "jet",
+            Backend::MdbExport => "mdb-export",
+            Backend::Odbc => "odbc",
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Backend::Jet => "Built-in",
+            Backend::MdbExport => "mdb-export",
+            Backend::Odbc => "ODBC",
+        }
+    }
+}
+
+impl std::fmt::Display for Backend {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl std::str::FromStr for Backend {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "jet" | "builtin" | "built-in" => Ok(Backend::Jet),
+            "mdb-export" | "mdbexport" | "mdbtools" => Ok(Backend::MdbExport),
+            "odbc" => Ok(Backend::Odbc),
+            other => Err(anyhow::anyhow!("Unknown backend '{}' (expected jet, mdb-export or odbc)", other)),
+        }
+    }
 }
 
 pub fn read_table<T: for<'de> Deserialize<'de>>(file_path: &str, table_name: &str) -> Result<Vec<T>> {
-    // On Windows, prioritize ODBC since we don't bundle mdbtools anymore
-    if cfg!(target_os = "windows") {
-        return read_table_fallback(file_path, table_name);
+    read_table_with(Backend::from_env(), file_path, table_name)
+}
+
+pub fn read_table_with<T: for<'de> Deserialize<'de>>(backend: Backend, file_path: &str, table_name: &str) -> Result<Vec<T>> {
+    match backend {
+        Backend::Jet => read_table_jet(file_path, table_name),
+        Backend::MdbExport => read_table_mdb_export(file_path, table_name),
+        Backend::Odbc => read_table_fallback(file_path, table_name),
+    }
+}
+
+pub fn list_tables(file_path: &str) -> Result<Vec<String>> {
+    list_tables_with(Backend::from_env(), file_path)
+}
+
+pub fn list_tables_with(backend: Backend, file_path: &str) -> Result<Vec<String>> {
+    match backend {
+        Backend::Jet => JetFile::open(file_path)?.table_names(),
+        Backend::MdbExport => {
+            let output = Command::new(get_mdb_tables_command())
+                .arg("-1")
+                .arg(file_path)
+                .output()
+                .context("Failed to execute mdb-tables")?;
+            if !output.status.success() {
+                anyhow::bail!("mdb-tables failed: {}", String::from_utf8_lossy(&output.stderr));
+            }
+            Ok(String::from_utf8_lossy(&output.stdout)
+                .lines()
+                .map(|l| l.trim().to_string())
+                .filter(|l| !l.is_empty())
+                .collect())
+        }
+        Backend::Odbc => list_tables_fallback(file_path),
+    }
+}
+
+pub(crate) fn deserialize_csv<T: for<'de> Deserialize<'de>>(data: &[u8], table_name: &str) -> Result<Vec<T>> {
+    let mut reader = csv::Reader::from_reader(data);
+    let mut results = Vec::new();
+    for result in reader.deserialize() {
+        let record: T = result.with_context(|| format!("Failed to deserialize CSV record in table {}", table_name))?;
+        results.push(record);
     }
+    Ok(results)
+}
+
+fn read_table_jet<T: for<'de> Deserialize<'de>>(file_path: &str, table_name: &str) -> Result<Vec<T>> {
+    let jet = JetFile::open(file_path)?;
+    read_jet_table(&jet, table_name)
+        .with_context(|| format!("Failed to read table {} from {}", table_name, file_path))
+}
 
-    // Try mdb-export on other platforms (or if fallback explicitly called later? No, strict separation)
+pub fn read_jet_table<T: for<'de> Deserialize<'de>>(jet: &JetFile, table_name: &str) -> Result<Vec<T>> {
+    let table = jet.read_table(table_name)?;
+
+    // Go through CSV so the serde structs see exactly what mdb-export would give them.
+    let mut wtr = csv::WriterBuilder::new().from_writer(Vec::new());
+    wtr.write_record(table.column_names())?;
+    for row in &table.rows {
+        wtr.write_record(row.iter().map(|v| v.as_deref().unwrap_or("")))?;
+    }
+    let csv_data = wtr.into_inner()?;
+    if std::env::var("DEBUG_CSV").is_ok() {
+        println!("CSV Output for {}: {}", table_name, String::from_utf8_lossy(&csv_data));
+    }
+    deserialize_csv(&csv_data, table_name)
+}
+
+fn read_table_mdb_export<T: for<'de> Deserialize<'de>>(file_path: &str, table_name: &str) -> Result<Vec<T>> {
     let output = Command::new(get_mdb_export_command())
         .arg(file_path)
         .arg(table_name)
@@ -161,13 +351,7 @@ pub fn read_table<T: for<'de> Deserialize<'de>>(file_path: &str, table_name: &st
             if std::env::var("DEBUG_CSV").is_ok() {
                 println!("CSV Output for {}: {}", table_name, String::from_utf8_lossy(&output.stdout));
             }
-            let mut reader = csv::Reader::from_reader(output.stdout.as_slice());
-            let mut results = Vec::new();
-            for result in reader.deserialize() {
-                let record: T = result.with_context(|| format!("Failed to deserialize CSV record in table {}", table_name))?;
-                results.push(record);
-            }
-            Ok(results)
+            deserialize_csv(&output.stdout, table_name)
         }
         err => {
             match err {
@@ -181,16 +365,41 @@ pub fn read_table<T: for<'de> Deserialize<'de>>(file_path: &str, table_name: &st
 }
 
 #[cfg(target_os = "windows")]
-use odbc_api::{Environment, ConnectionOptions, Cursor, ResultSetMetadata};
+use odbc_api::{Environment, Connection, ConnectionOptions, Cursor, ResultSetMetadata};
 #[cfg(target_os = "windows")]
 use odbc_api::buffers::TextRowSet;
 
+#[cfg(target_os = "windows")]
+fn connect_odbc<'env>(env: &'env Environment, file_path: &str) -> Result<Connection<'env>> {
+    let conn_string = format!("Driver={{Microsoft Access Driver (*.mdb, *.accdb)}};Dbq={};", file_path);
+    env.connect_with_connection_string(&conn_string, ConnectionOptions::default())
+        .context("Failed to connect to Access DB via ODBC. Ensure Microsoft Access Database Engine 2016 Redistributable is installed.")
+}
+
+#[cfg(target_os = "windows")]
+fn list_tables_fallback(file_path: &str) -> Result<Vec<String>> {
+    let env = Environment::new()?;
+    let conn = connect_odbc(&env, file_path)?;
+    let mut cursor = conn.tables("", "", "", "TABLE")?;
+
+    let mut names = Vec::new();
+    let mut buffers = TextRowSet::for_cursor(100, &mut cursor, Some(256))?;
+    let mut row_set_cursor = cursor.bind_buffer(&mut buffers)?;
+    while let Some(batch) = row_set_cursor.fetch()? {
+        for i in 0..batch.num_rows() {
+            // Column 3 of SQLTables is TABLE_NAME
+            if let Some(name) = batch.at(2, i) {
+                names.push(String::from_utf8_lossy(name).to_string());
+            }
+        }
+    }
+    Ok(names)
+}
+
 #[cfg(target_os = "windows")]
 fn read_table_fallback<T: for<'de> Deserialize<'de>>(file_path: &str, table_name: &str) -> Result<Vec<T>> {
     let env = Environment::new()?;
-    let conn_string = format!("Driver={{Microsoft Access Driver (*.mdb, *.accdb)}};Dbq={};", file_path);
-    let conn = env.connect_with_connection_string(&conn_string, ConnectionOptions::default())
-        .context("Failed to connect to Access DB via ODBC. Ensure Microsoft Access Database Engine 2016 Redistributable is installed.")?;
+    let conn = connect_odbc(&env, file_path)?;
 
     let query = format!("SELECT * FROM [{}]", table_name);
     let maybe_cursor = conn.execute(&query, ())?;
@@ -244,3 +453,39 @@ fn read_table_fallback<T: for<'de> Deserialize<'de>>(file_path: &str, table_name
 fn read_table_fallback<T: for<'de> Deserialize<'de>>(_file_path: &str, _table_name: &str) -> Result<Vec<T>> {
    Err(anyhow::anyhow!("ODBC fallback not supported on this OS"))
 }
+
+#[cfg(not(target_os = "windows"))]
+fn list_tables_fallback(_file_path: &str) -> Result<Vec<String>> {
+   Err(anyhow::anyhow!("ODBC fallback not supported on this OS"))
+}
+
+/// Writes new Temps/Rang values to TPatVagues in one transaction.
+#[cfg(target_os = "windows")]
+pub fn update_pat_vagues(file_path: &str, updates: &[crate::write_back::ResultUpdate]) -> Result<()> {
+    let env = Environment::new()?;
+    let conn = connect_odbc(&env, file_path)?;
+    conn.set_autocommit(false)?;
+
+    let result = (|| -> Result<()> {
+        let mut prepared = conn.prepare("UPDATE [TPatVagues] SET [Temps] = ?, [Rang] = ? WHERE [CleTPatVagues] = ?")?;
+        for update in updates {
+            // Temps is a Double; an f32 would store 0.4411 slightly off
+            prepared.execute((&update.new_time, &update.new_rank, &update.lane_id))
+                .with_context(|| format!("Failed to update TPatVagues row {}", update.lane_id))?;
+        }
+        Ok(())
+    })();
+
+    match result {
+        Ok(()) => conn.commit().context("Failed to commit TPatVagues updates"),
+        Err(e) => {
+            let _ = conn.rollback();
+            Err(e)
+        }
+    }
+}
+
+#[cfg(not(target_os = "windows"))]
+pub fn update_pat_vagues(_file_path: &str, _updates: &[crate::write_back::ResultUpdate]) -> Result<()> {
+   Err(anyhow::anyhow!("Writing to .pat files needs the Access ODBC driver, which is only available on Windows"))
+}
@@ -0,0 +1,184 @@
+use crate::change::FileStamp;
+use crate::jet::JetFile;
+use anyhow::{Context, Result};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+
+const COPY_ATTEMPTS: u32 = 5;
+const RETRY_DELAY: Duration = Duration::from_millis(250);
+
+/// A private copy of a .pat file taken while GCPV may be writing to it.
+/// The copy is deleted on drop unless it was created with `keep`.
+pub struct ConsistentCopy {
+    path: PathBuf,
+    keep: bool,
+}
+
+impl ConsistentCopy {
+    /// Copies `file_path` to the temp folder, retrying while the file is locked
+    /// or changes during the copy, and checks the copy is a readable database.
+    pub fn create(file_path: &str, keep: bool) -> Result<Self> {
+        Self::create_in(file_path, keep, &std::env::temp_dir().join("gcpv-lynx-export"), RETRY_DELAY)
+    }
+
+    fn create_in(file_path: &str, keep: bool, dir: &Path, retry_delay: Duration) -> Result<Self> {
+        std::fs::create_dir_all(dir)
+            .with_context(|| format!("Failed to create {}", dir.display()))?;
+
+        let stem = Path::new(file_path).file_stem().and_then(|s| s.to_str()).unwrap_or("snapshot");
+        let stamp = chrono::Local::now().format("%Y%m%d-%H%M%S%.3f");
+        let copy = Self {
+            path: dir.join(format!("{}-{}.pat", stem, stamp)),
+            keep,
+        };
+
+        retry(COPY_ATTEMPTS, retry_delay, || copy.try_copy(file_path))
+            .with_context(|| format!("Could not take a consistent copy of {}", file_path))?;
+        Ok(copy)
+    }
+
+    fn try_copy(&self, file_path: &str) -> Result<()> {
+        let before = FileStamp::of(file_path).context("Failed to read file metadata")?;
+        std::fs::copy(file_path, &self.path).context("Failed to copy file (is it locked?)")?;
+        let after = FileStamp::of(file_path).context("Failed to read file metadata")?;
+        if before != after {
+            anyhow::bail!("File changed while it was being copied");
+        }
+        if std::fs::metadata(&self.path)?.len() != before.size() {
+            anyhow::bail!("Copy is truncated");
+        }
+        JetFile::open(&self.path)
+            .and_then(|jet| jet.table_names())
+            .context("Copy is not a readable database")?;
+        Ok(())
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The path for backends that take a string; errors if it isn't UTF-8.
+    pub fn path_str(&self) -> Result<&str> {
+        self.path.to_str()
+            .with_context(|| format!("Snapshot copy path {} is not valid UTF-8", self.path.display()))
+    }
+}
+
+/// Runs `f` up to `attempts` times, waiting a little longer after each failure.
+/// Returns the last error if every attempt fails.
+fn retry<T>(attempts: u32, delay: Duration, mut f: impl FnMut() -> Result<T>) -> Result<T> {
+    let mut attempt = 1;
+    loop {
+        match f() {
+            Ok(value) => return Ok(value),
+            Err(e) if attempt < attempts => {
+                eprintln!("Snapshot copy attempt {} of {} failed: {:#}", attempt, attempts, e);
+                thread::sleep(delay * attempt);
+                attempt += 1;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+impl Drop for ConsistentCopy {
+    fn drop(&mut self) {
+        if !self.keep {
+            let _ = std::fs::remove_file(&self.path);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("gcpv-copy-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn pat_file() -> String {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("test-data");
+        let path = std::fs::read_dir(dir).unwrap()
+            .map(|entry| entry.unwrap().path())
+            .find(|path| path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("pat")))
+            .expect("no .pat files in test-data");
+        path.to_str().unwrap().to_string()
+    }
+
+    fn files_in(dir: &Path) -> usize {
+        std::fs::read_dir(dir).unwrap().count()
+    }
+
+    #[test]
+    fn copies_and_deletes_on_drop() {
+        let dir = temp_dir("delete");
+        let pat = pat_file();
+        let copy = ConsistentCopy::create_in(&pat, false, &dir, Duration::ZERO).unwrap();
+        assert!(copy.path().starts_with(&dir));
+        assert_eq!(std::fs::read(copy.path()).unwrap(), std::fs::read(&pat).unwrap());
+        assert_eq!(copy.path_str().unwrap(), copy.path().to_str().unwrap());
+
+        drop(copy);
+        assert_eq!(files_in(&dir), 0);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn keeps_copy_when_asked() {
+        let dir = temp_dir("keep");
+        let copy = ConsistentCopy::create_in(&pat_file(), true, &dir, Duration::ZERO).unwrap();
+        let path = copy.path().to_path_buf();
+        drop(copy);
+        assert!(path.exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn rejects_copies_that_are_not_databases() {
+        let dir = temp_dir("invalid");
+        let source = std::env::temp_dir().join(format!("gcpv-copy-source-{}.pat", std::process::id()));
+        std::fs::write(&source, "not a database").unwrap();
+
+        let err = ConsistentCopy::create_in(source.to_str().unwrap(), false, &dir, Duration::ZERO).err().unwrap();
+        assert!(format!("{:#}", err).contains("not a readable database"), "{:#}", err);
+        // The failed copy is cleaned up
+        assert_eq!(files_in(&dir), 0);
+
+        assert!(ConsistentCopy::create_in("missing.pat", false, &dir, Duration::ZERO).is_err());
+        std::fs::remove_file(&source).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn retries_until_an_attempt_succeeds() {
+        let mut calls = 0;
+        let result = retry(5, Duration::ZERO, || {
+            calls += 1;
+            if calls < 3 { anyhow::bail!("locked") } else { Ok(calls) }
+        });
+        assert_eq!(result.unwrap(), 3);
+
+        let mut calls = 0;
+        let result: Result<()> = retry(5, Duration::ZERO, || {
+            calls += 1;
+            anyhow::bail!("locked {}", calls)
+        });
+        assert_eq!(result.unwrap_err().to_string(), "locked 5");
+        assert_eq!(calls, 5);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn rejects_non_utf8_paths() {
+        use std::os::unix::ffi::OsStrExt;
+        let copy = ConsistentCopy {
+            path: PathBuf::from(std::ffi::OsStr::from_bytes(b"/tmp/comp-\xff.pat")),
+            keep: true,
+        };
+        assert!(copy.path_str().is_err());
+    }
+}
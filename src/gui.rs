@@ -1,51 +1,219 @@
 use eframe::egui;
-use std::path::PathBuf;
+use std::collections::HashMap;
 use std::time::{Duration, Instant};
 use std::thread;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex};
-use crate::app_logic::execute_cycle;
+use crate::app_logic::{apply_write_back, execute_file_cycle, list_file_competitions, plan_write_back, CycleOutcome, OutputPaths, ReadOptions};
+use crate::change::ChangeTracker;
+use crate::db::Backend;
+use crate::logic::CompetitionInfo;
+use crate::logos::{LogoKey, LogoOptions};
+use crate::settings::{ExporterSettings, Profiles, PROFILES_KEY, SETTINGS_KEY};
+use crate::race_time::TimePrecision;
+use crate::text::{NameOptions, OutputEncoding};
+use crate::watcher::{FileWatcher, WatchMode};
+use crate::write_back::{OdbcResultWriter, WriteBackPlan};
+use crate::writer::{ExportOptions, WithdrawnPolicy};
+
+/// Storage key for the competition chosen per .pat file.
+pub const COMPETITIONS_KEY: &str = "competitions";
 
 pub struct GcpvApp {
     pat_file: String,
+    competitions: Vec<CompetitionInfo>,
+    // None picks the only competition in the file
+    competition_id: Option<i32>,
+    remembered_competitions: HashMap<String, i32>,
     output_folder: String,
     interval_seconds: u64,
+    read_options: ReadOptions,
+    export_options: ExportOptions,
+    watch_mode: WatchMode,
     running: bool,
     last_run: Option<Instant>,
+    watcher: Option<FileWatcher>,
+    // A change was seen while a cycle was still processing
+    pending_run: bool,
     status_message: String,
     // Thread handling
     is_processing: Arc<Mutex<bool>>,
+    // Only the worker locks the tracker; the UI asks for a reset through the flag
+    tracker: Arc<Mutex<ChangeTracker>>,
+    tracker_reset: Arc<AtomicBool>,
+    cycle_status: Arc<Mutex<Option<String>>>,
+    // Writing Lynx results back to the .pat file
+    write_back_plan: Option<WriteBackPlan>,
+    write_back_message: String,
+    profiles: Profiles,
+    new_profile_name: String,
 }
 
 impl Default for GcpvApp {
     fn default() -> Self {
+        let settings = ExporterSettings::default();
         Self {
-            pat_file: "".to_owned(),
-            output_folder: "".to_owned(),
-            interval_seconds: 60,
+            pat_file: settings.pat_file,
+            competitions: Vec::new(),
+            competition_id: settings.competition_id,
+            remembered_competitions: HashMap::new(),
+            output_folder: settings.output_folder,
+            interval_seconds: settings.interval_seconds,
+            read_options: settings.read_options,
+            export_options: settings.export_options,
+            watch_mode: settings.watch_mode,
             running: false,
             last_run: None,
+            watcher: None,
+            pending_run: false,
             status_message: "Ready".to_owned(),
             is_processing: Arc::new(Mutex::new(false)),
+            tracker: Arc::new(Mutex::new(ChangeTracker::new())),
+            tracker_reset: Arc::new(AtomicBool::new(false)),
+            cycle_status: Arc::new(Mutex::new(None)),
+            write_back_plan: None,
+            write_back_message: "".to_owned(),
+            profiles: Profiles::default(),
+            new_profile_name: "".to_owned(),
         }
     }
 }
 
 impl GcpvApp {
-    pub fn new(_cc: &eframe::CreationContext<'_>) -> Self {
+    /// `pat_file` (e.g. a file opened from Explorer) replaces the saved one.
+    pub fn new(cc: &eframe::CreationContext<'_>, pat_file: Option<String>) -> Self {
         // Customize egui here with cc.egui_ctx.set_fonts and cc.egui_ctx.set_visuals.
-        // Restore app state if using persistence (not implemented yet).
-        Self::default()
+        let mut app = Self::default();
+        if let Some(storage) = cc.storage {
+            app.remembered_competitions = eframe::get_value(storage, COMPETITIONS_KEY).unwrap_or_default();
+            app.profiles = eframe::get_value(storage, PROFILES_KEY).unwrap_or_default();
+            if let Some(settings) = eframe::get_value(storage, SETTINGS_KEY) {
+                app.apply_settings(settings);
+            }
+        }
+        if let Some(pat_file) = pat_file {
+            app.pat_file = pat_file;
+            app.refresh_competitions();
+            app.reset_tracker();
+        }
+        app
+    }
+
+    fn settings(&self) -> ExporterSettings {
+        ExporterSettings {
+            pat_file: self.pat_file.clone(),
+            competition_id: self.competition_id,
+            output_folder: self.output_folder.clone(),
+            interval_seconds: self.interval_seconds,
+            watch_mode: self.watch_mode,
+            read_options: self.read_options,
+            export_options: self.export_options.clone(),
+            ..Default::default()
+        }
+    }
+
+    fn apply_settings(&mut self, settings: ExporterSettings) {
+        self.pat_file = settings.pat_file;
+        self.output_folder = settings.output_folder;
+        self.interval_seconds = settings.interval_seconds;
+        self.watch_mode = settings.watch_mode;
+        self.read_options = settings.read_options;
+        self.export_options = settings.export_options;
+        self.write_back_plan = None;
+        self.write_back_message.clear();
+        self.refresh_competitions();
+        self.competition_id = settings_competition(&self.competitions, settings.competition_id);
+        remember_competition(&mut self.remembered_competitions, &self.pat_file, self.competition_id);
+        self.reset_tracker();
+    }
+
+    /// Forces the next cycle to run. Doesn't block while a cycle is running.
+    fn reset_tracker(&self) {
+        self.tracker_reset.store(true, Ordering::SeqCst);
+    }
+
+    fn refresh_competitions(&mut self) {
+        match load_competitions(&self.pat_file, &self.read_options, &self.remembered_competitions) {
+            Ok((competitions, selected)) => {
+                self.competitions = competitions;
+                self.competition_id = selected;
+            }
+            Err(e) => {
+                self.competitions.clear();
+                self.competition_id = None;
+                self.status_message = format!("Error reading competitions: {}", e);
+            }
+        }
+    }
+
+    fn results_dir(&self) -> &str {
+        // Lynx usually writes its .LIF files next to the LYNX.EVT it reads
+        let results_folder = &self.export_options.results_folder;
+        if results_folder.is_empty() { &self.output_folder } else { results_folder }
+    }
+
+    fn preview_write_back(&mut self) {
+        self.write_back_plan = None;
+        match plan_write_back(&self.pat_file, &self.read_options, self.results_dir(), self.competition_id) {
+            Ok(plan) => {
+                self.write_back_message = format!("{} change(s) to write", plan.updates.len());
+                self.write_back_plan = Some(plan);
+            }
+            Err(e) => self.write_back_message = format!("Error: {}", e),
+        }
+    }
+
+    fn apply_write_back(&mut self) {
+        let Some(plan) = self.write_back_plan.take() else {
+            return;
+        };
+        self.write_back_message = match apply_write_back(&OdbcResultWriter::new(&self.pat_file), &plan) {
+            Ok(backups) if backups.is_empty() => format!("Wrote {} result(s), no backup made", plan.updates.len()),
+            Ok(backups) => {
+                let backups: Vec<String> = backups.iter().map(|b| b.display().to_string()).collect();
+                format!("Wrote {} result(s), backup at {}", plan.updates.len(), backups.join(", "))
+            }
+            Err(e) => format!("Error: {}", e),
+        };
+    }
+
+    fn write_back_ui(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            let ready = !self.pat_file.is_empty() && !self.results_dir().is_empty();
+            if ui.add_enabled(ready, egui::Button::new("Preview")).clicked() {
+                self.preview_write_back();
+            }
+            let can_write = self.write_back_plan.as_ref().is_some_and(|p| !p.is_empty());
+            if ui.add_enabled(can_write, egui::Button::new("Write to .pat"))
+                .on_hover_text("Backs up the .pat file, then updates Temps and Rang")
+                .clicked()
+            {
+                self.apply_write_back();
+            }
+        });
+        if !self.write_back_message.is_empty() {
+            ui.label(&self.write_back_message);
+        }
+        if let Some(plan) = &self.write_back_plan {
+            egui::ScrollArea::vertical().max_height(150.0).show(ui, |ui| {
+                ui.monospace(plan.to_string());
+            });
+        }
     }
 
     fn run_conversion(&mut self) {
         let pat_file = self.pat_file.clone();
         let output_folder = self.output_folder.clone();
+        let read_options = self.read_options;
+        let mut export_options = self.export_options.clone();
+        export_options.results_folder = self.results_dir().to_string();
+        let competition_id = self.competition_id;
         let is_processing = self.is_processing.clone();
+        let tracker = self.tracker.clone();
+        let tracker_reset = self.tracker_reset.clone();
+        let cycle_status = self.cycle_status.clone();
 
-        // Update status immediately?
-        // We can't easily update self.status_message from thread without a channel or Arc<Mutex>.
-        // For simplicity, we just set a flag.
-        
+        // The thread reports back through cycle_status, picked up on the next frame.
         {
             let mut processing = is_processing.lock().unwrap();
             if *processing {
@@ -55,52 +223,91 @@ impl GcpvApp {
         }
 
         thread::spawn(move || {
-            let evt_path = PathBuf::from(&output_folder).join("LYNX.EVT");
-            let json_path = PathBuf::from(&output_folder).join("races.json");
+            let outputs = OutputPaths::in_folder(&output_folder);
             
             // We can't access env vars easily if they were relied upon for competition ID overrides,
             // strictly speaking the GUI should probably expose that if needed. 
             // For now passing None for env_competition_id.
             
-            let result = execute_cycle(
-                &pat_file, 
-                evt_path.to_str().unwrap(), 
-                json_path.to_str().unwrap(), 
-                None
+            let mut tracker = tracker.lock().unwrap();
+            if tracker_reset.swap(false, Ordering::SeqCst) {
+                tracker.reset();
+            }
+            let result = execute_file_cycle(
+                &pat_file,
+                &read_options,
+                &outputs,
+                &export_options,
+                competition_id,
+                &mut tracker
             );
+            drop(tracker);
 
             // Access lock to finish
             let mut processing = is_processing.lock().unwrap();
             *processing = false;
             
-            // In a real app we might send the result back via a channel to display error/success.
-            // For now just print to stdout
-             match result {
-                Ok(_) => println!("Conversion successful"),
-                Err(e) => eprintln!("Conversion failed: {:?}", e),
-            }
+            let now = chrono::Local::now().format("%H:%M:%S");
+            let status = match result {
+                Ok(CycleOutcome::Updated) => format!("Updated at {}", now),
+                Ok(CycleOutcome::Unchanged) => format!("No changes (checked at {})", now),
+                Err(e) => {
+                    eprintln!("Conversion failed: {:?}", e);
+                    format!("Error: {}", e)
+                }
+            };
+            *cycle_status.lock().unwrap() = Some(status);
         });
     }
 }
 
 impl eframe::App for GcpvApp {
+    fn save(&mut self, storage: &mut dyn eframe::Storage) {
+        eframe::set_value(storage, COMPETITIONS_KEY, &self.remembered_competitions);
+        eframe::set_value(storage, SETTINGS_KEY, &self.settings());
+        eframe::set_value(storage, PROFILES_KEY, &self.profiles);
+    }
+
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
         egui::CentralPanel::default().show(ctx, |ui| {
             ui.heading("GCPV Lynx Export");
             
             ui.add_space(10.0);
 
+            let current = self.settings();
+            let loaded = ui.add_enabled_ui(!self.running, |ui| {
+                profiles_ui(ui, &mut self.profiles, &mut self.new_profile_name, &current)
+            }).inner;
+            if let Some(settings) = loaded {
+                self.apply_settings(settings);
+            }
+
             // File Selection
             ui.horizontal(|ui| {
                 ui.label("PAT File:");
-                ui.text_edit_singleline(&mut self.pat_file);
+                if ui.text_edit_singleline(&mut self.pat_file).lost_focus() {
+                    self.refresh_competitions();
+                }
                 if ui.button("Select...").clicked() {
                     if let Some(path) = rfd::FileDialog::new().add_filter("PAT", &["pat"]).pick_file() {
                         self.pat_file = path.display().to_string();
+                        self.refresh_competitions();
                     }
                 }
             });
 
+            // Competition
+            ui.horizontal(|ui| {
+                ui.label("Competition:");
+                if competition_picker(ui, &self.competitions, &mut self.competition_id) {
+                    remember_competition(&mut self.remembered_competitions, &self.pat_file, self.competition_id);
+                    self.reset_tracker();
+                }
+                if ui.button("Refresh").clicked() {
+                    self.refresh_competitions();
+                }
+            });
+
             // Output Folder Selection
             ui.horizontal(|ui| {
                 ui.label("Output Folder:");
@@ -112,12 +319,117 @@ impl eframe::App for GcpvApp {
                 }
             });
 
+            ui.horizontal(|ui| {
+                ui.label("Lynx results folder:");
+                let changed = ui.add(egui::TextEdit::singleline(&mut self.export_options.results_folder).hint_text("Same as output folder"))
+                    .on_hover_text("FinishLynx .LIF files; their results go in races.json until they are entered in GCPV")
+                    .changed();
+                if ui.button("Select...").clicked() {
+                    if let Some(path) = rfd::FileDialog::new().pick_folder() {
+                        self.export_options.results_folder = path.display().to_string();
+                        self.reset_tracker();
+                    }
+                }
+                if changed {
+                    self.reset_tracker();
+                }
+            });
+
             // Interval
             ui.horizontal(|ui| {
-                ui.label("Interval (seconds):");
+                ui.label("Fallback interval (seconds):");
                 ui.add(egui::DragValue::new(&mut self.interval_seconds).range(1..=3600));
             });
 
+            // File watching
+            ui.horizontal(|ui| {
+                ui.label("Watch:");
+                egui::ComboBox::from_id_salt("watch_mode")
+                    .selected_text(self.watch_mode.label())
+                    .show_ui(ui, |ui| {
+                        for mode in WatchMode::ALL {
+                            ui.selectable_value(&mut self.watch_mode, mode, mode.label());
+                        }
+                    });
+            });
+
+            // Database reader
+            ui.horizontal(|ui| {
+                ui.label("Reader:");
+                egui::ComboBox::from_id_salt("backend")
+                    .selected_text(self.read_options.backend.label())
+                    .show_ui(ui, |ui| {
+                        for backend in Backend::ALL {
+                            ui.selectable_value(&mut self.read_options.backend, backend, backend.label());
+                        }
+                    });
+            });
+
+            ui.horizontal(|ui| {
+                ui.checkbox(&mut self.read_options.snapshot_copy, "Read from a copy of the file")
+                    .on_hover_text("Copy the .pat to a temp folder first, so GCPV writes can't be read half-way");
+                if self.read_options.snapshot_copy {
+                    ui.checkbox(&mut self.read_options.keep_copy, "Keep copies for debugging");
+                }
+            });
+
+            egui::CollapsingHeader::new("Write Lynx results to GCPV").show(ui, |ui| {
+                self.write_back_ui(ui);
+            });
+
+            ui.horizontal(|ui| {
+                ui.label("Withdrawn skaters:");
+                let before = self.export_options.withdrawn;
+                egui::ComboBox::from_id_salt("withdrawn")
+                    .selected_text(self.export_options.withdrawn.label())
+                    .show_ui(ui, |ui| {
+                        for policy in WithdrawnPolicy::ALL {
+                            ui.selectable_value(&mut self.export_options.withdrawn, policy, policy.label());
+                        }
+                    });
+                if self.export_options.withdrawn != before {
+                    self.reset_tracker();
+                }
+            });
+
+            ui.horizontal(|ui| {
+                ui.label("Time precision:");
+                let before = self.export_options.precision;
+                egui::ComboBox::from_id_salt("precision")
+                    .selected_text(self.export_options.precision.label())
+                    .show_ui(ui, |ui| {
+                        for precision in TimePrecision::ALL {
+                            ui.selectable_value(&mut self.export_options.precision, precision, precision.label());
+                        }
+                    });
+                if self.export_options.precision != before {
+                    self.reset_tracker();
+                }
+            });
+
+            egui::CollapsingHeader::new("Names and encoding").show(ui, |ui| {
+                let before = self.export_options.encoding;
+                ui.horizontal(|ui| {
+                    ui.label("Lynx file encoding:");
+                    egui::ComboBox::from_id_salt("encoding")
+                        .selected_text(self.export_options.encoding.label())
+                        .show_ui(ui, |ui| {
+                            for encoding in OutputEncoding::ALL {
+                                ui.selectable_value(&mut self.export_options.encoding, encoding, encoding.label());
+                            }
+                        });
+                });
+                if name_options_ui(ui, &mut self.export_options.names) || self.export_options.encoding != before {
+                    self.reset_tracker();
+                }
+            });
+
+            egui::CollapsingHeader::new("Logos").show(ui, |ui| {
+                if logo_options_ui(ui, &mut self.export_options.logos) {
+                    self.reset_tracker();
+                }
+            });
+
             ui.add_space(20.0);
 
             // Start/Stop
@@ -125,6 +437,7 @@ impl eframe::App for GcpvApp {
                 if self.running {
                     if ui.button("Stop").clicked() {
                         self.running = false;
+                        self.watcher = None;
                         self.status_message = "Stopped".to_string();
                     }
                     ui.spinner();
@@ -134,14 +447,28 @@ impl eframe::App for GcpvApp {
                             self.status_message = "Error: Please select file and output folder".to_string();
                         } else {
                             self.running = true;
-                            self.status_message = "Running...".to_string();
                             self.last_run = None; // Trigger immediate run
+                            self.reset_tracker(); // Settings may have changed
+                            self.status_message = "Running...".to_string();
+                            self.watcher = match FileWatcher::new(&self.pat_file, self.watch_mode) {
+                                Ok(watcher) => Some(watcher),
+                                Err(e) => {
+                                    eprintln!("Could not watch file: {:?}", e);
+                                    None
+                                }
+                            };
                         }
                     }
                 }
             });
 
+            if let Some(status) = self.cycle_status.lock().unwrap().take() {
+                self.status_message = status;
+            }
             ui.label(&self.status_message);
+            if self.running {
+                ui.label(watch_status(self.watcher.as_ref(), self.interval_seconds));
+            }
             
             // Check processing status
             let is_processing = *self.is_processing.lock().unwrap();
@@ -151,13 +478,18 @@ impl eframe::App for GcpvApp {
 
             // Background Logic
             if self.running {
+                if self.watcher.as_ref().map(|w| w.poll_changed()).unwrap_or(false) {
+                    self.pending_run = true;
+                }
+
                 let now = Instant::now();
-                let should_run = match self.last_run {
+                let should_run = self.pending_run || match self.last_run {
                     Some(last) => now.duration_since(last) >= Duration::from_secs(self.interval_seconds),
                     None => true,
                 };
 
                 if should_run && !is_processing {
+                    self.pending_run = false;
                     self.last_run = Some(now);
                     self.run_conversion();
                 }
@@ -168,3 +500,157 @@ impl eframe::App for GcpvApp {
         });
     }
 }
+
+fn watch_status(watcher: Option<&FileWatcher>, interval_seconds: u64) -> String {
+    match watcher {
+        Some(w) => format!("Watching file ({}), checking every {}s as fallback", w.mode().label(), interval_seconds),
+        None => format!("File watching unavailable, checking every {}s", interval_seconds),
+    }
+}
+
+/// Reads the competitions in a .pat file and picks the remembered one, if still present.
+pub fn load_competitions(
+    pat_file: &str,
+    read_options: &ReadOptions,
+    remembered: &HashMap<String, i32>,
+) -> anyhow::Result<(Vec<CompetitionInfo>, Option<i32>)> {
+    if pat_file.is_empty() {
+        return Ok((Vec::new(), None));
+    }
+    let competitions = list_file_competitions(pat_file, read_options)?;
+    let selected = remembered.get(pat_file)
+        .copied()
+        .filter(|id| competitions.iter().any(|c| c.id == *id))
+        .or_else(|| competitions.first().map(|c| c.id).filter(|_| competitions.len() > 1));
+    Ok((competitions, selected))
+}
+
+/// The competition saved in settings, unless the file was read and no longer has it.
+pub fn settings_competition(competitions: &[CompetitionInfo], saved: Option<i32>) -> Option<i32> {
+    match saved {
+        Some(id) if competitions.is_empty() || competitions.iter().any(|c| c.id == id) => Some(id),
+        _ => competitions.first().map(|c| c.id).filter(|_| competitions.len() > 1),
+    }
+}
+
+pub fn remember_competition(remembered: &mut HashMap<String, i32>, pat_file: &str, competition_id: Option<i32>) {
+    match competition_id {
+        Some(id) => remembered.insert(pat_file.to_string(), id),
+        None => remembered.remove(pat_file),
+    };
+}
+
+/// Competition dropdown shared by both GUIs. Returns true when the selection changed.
+pub fn competition_picker(ui: &mut egui::Ui, competitions: &[CompetitionInfo], selected: &mut Option<i32>) -> bool {
+    let before = *selected;
+    let selected_text = match *selected {
+        Some(id) => competitions.iter()
+            .find(|c| c.id == id)
+            .map(|c| c.label())
+            .unwrap_or_else(|| format!("Competition {}", id)),
+        None => "Automatic".to_string(),
+    };
+    egui::ComboBox::from_id_salt("competition")
+        .selected_text(selected_text)
+        .width(300.0)
+        .show_ui(ui, |ui| {
+            ui.selectable_value(selected, None, "Automatic");
+            for competition in competitions {
+                ui.selectable_value(selected, Some(competition.id), competition.label());
+            }
+        });
+    before != *selected
+}
+
+/// Logo settings shared by both GUIs. Returns true when anything changed.
+pub fn logo_options_ui(ui: &mut egui::Ui, logos: &mut LogoOptions) -> bool {
+    let before = logos.clone();
+    ui.horizontal(|ui| {
+        ui.label("Logo folder or URL:");
+        ui.add(egui::TextEdit::singleline(&mut logos.base).hint_text("https://host/logos/{affiliation}.png"))
+            .on_hover_text("A folder (<folder>/<name>.png) or a template using {key}, {affiliation}, {club_id} or {region}");
+        if ui.button("Select...").clicked() {
+            if let Some(path) = rfd::FileDialog::new().pick_folder() {
+                logos.base = path.display().to_string();
+            }
+        }
+    });
+    ui.horizontal(|ui| {
+        ui.label("Logo by:");
+        egui::ComboBox::from_id_salt("logo_key")
+            .selected_text(logos.key.label())
+            .show_ui(ui, |ui| {
+                for key in LogoKey::ALL {
+                    ui.selectable_value(&mut logos.key, key, key.label());
+                }
+            });
+    });
+    ui.horizontal(|ui| {
+        ui.label("Fallback logo:");
+        ui.text_edit_singleline(&mut logos.fallback);
+        if ui.button("Select...").clicked() {
+            if let Some(path) = rfd::FileDialog::new().add_filter("Image", &["png", "jpg", "svg"]).pick_file() {
+                logos.fallback = path.display().to_string();
+            }
+        }
+    });
+    *logos != before
+}
+
+/// Name clean-up settings shared by both GUIs. Returns true when anything changed.
+pub fn name_options_ui(ui: &mut egui::Ui, names: &mut NameOptions) -> bool {
+    let before = names.clone();
+    ui.checkbox(&mut names.uppercase_last_names, "Uppercase last names");
+    ui.checkbox(&mut names.strip_commas, "Remove commas from names");
+    ui.checkbox(&mut names.trim, "Trim extra spaces");
+    *names != before
+}
+
+/// Profile picker shared by both GUIs. Returns the settings to load when a
+/// profile is picked.
+pub fn profiles_ui(
+    ui: &mut egui::Ui,
+    profiles: &mut Profiles,
+    new_name: &mut String,
+    current: &ExporterSettings,
+) -> Option<ExporterSettings> {
+    let mut loaded = None;
+    let names: Vec<String> = profiles.names().map(str::to_string).collect();
+    let selected = profiles.selected.clone();
+    ui.horizontal(|ui| {
+        ui.label("Profile:");
+        let selected_text = match &selected {
+            Some(name) if profiles.get(name) != Some(current) => format!("{} (modified)", name),
+            Some(name) => name.clone(),
+            None => "None".to_string(),
+        };
+        egui::ComboBox::from_id_salt("profile")
+            .selected_text(selected_text)
+            .width(200.0)
+            .show_ui(ui, |ui| {
+                for name in &names {
+                    if ui.selectable_label(selected.as_ref() == Some(name), name).clicked() {
+                        loaded = profiles.select(name);
+                    }
+                }
+            });
+        if let Some(name) = &selected {
+            if ui.button("Save").on_hover_text("Overwrite this profile with the current settings").clicked() {
+                // Only fails on an empty name, which can't be selected
+                profiles.save(name, current.clone()).ok();
+            }
+            if ui.button("Delete").clicked() {
+                profiles.delete(name);
+            }
+        }
+    });
+    ui.horizontal(|ui| {
+        ui.add(egui::TextEdit::singleline(new_name).hint_text("New profile name"));
+        if ui.add_enabled(!new_name.trim().is_empty(), egui::Button::new("Save as")).clicked()
+            && profiles.save(new_name, current.clone()).is_ok()
+        {
+            new_name.clear();
+        }
+    });
+    loaded
+}
@@ -0,0 +1,853 @@
+// Read-only reader for Jet3 / Jet4 (Access 97 - 2003) database files.
+//
+// GCPV stores its competitions in .pat files, which are plain Jet databases.
+// This module walks the pages directly so tables can be read without
+// mdb-export or the Access ODBC driver. Only what the exporter needs is
+// supported: listing user tables and decoding the rows of a table as text,
+// formatted the same way mdb-export prints them.
+
+use anyhow::{bail, Context, Result};
+use chrono::{NaiveDate, TimeDelta};
+use std::path::Path;
+
+const MSYS_OBJECTS_PAGE: u32 = 2;
+const PAGE_TYPE_DATA: u8 = 0x01;
+const PAGE_TYPE_TDEF: u8 = 0x02;
+const PAGE_TYPE_USAGE_MAP: u8 = 0x05;
+
+const ROW_OFFSET_MASK: u16 = 0x1fff;
+const ROW_DELETED: u16 = 0x8000;
+const ROW_LOOKUP: u16 = 0x4000;
+
+const MEMO_OVERHEAD: usize = 12;
+const MEMO_INLINE: u32 = 0x8000_0000;
+const MEMO_SINGLE_PAGE: u32 = 0x4000_0000;
+
+const COL_FLAG_FIXED: u8 = 0x01;
+
+// Column types
+const COL_BOOL: u8 = 0x01;
+const COL_BYTE: u8 = 0x02;
+const COL_INT: u8 = 0x03;
+const COL_LONG: u8 = 0x04;
+const COL_MONEY: u8 = 0x05;
+const COL_FLOAT: u8 = 0x06;
+const COL_DOUBLE: u8 = 0x07;
+const COL_DATETIME: u8 = 0x08;
+const COL_BINARY: u8 = 0x09;
+const COL_TEXT: u8 = 0x0a;
+const COL_OLE: u8 = 0x0b;
+const COL_MEMO: u8 = 0x0c;
+const COL_GUID: u8 = 0x0f;
+const COL_NUMERIC: u8 = 0x10;
+
+// MSysObjects
+const OBJECT_TYPE_TABLE: i64 = 1;
+const SYSTEM_OBJECT_FLAGS: i64 = 0x8000_0002;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JetVersion {
+    Jet3,
+    Jet4,
+}
+
+/// Byte offsets that differ between the Jet3 and Jet4 page layouts.
+struct Layout {
+    page_size: usize,
+    data_row_count: usize,
+    tdef_num_cols: usize,
+    tdef_num_real_idx: usize,
+    tdef_used_pages: usize,
+    tdef_real_idx_start: usize,
+    real_idx_entry: usize,
+    col_entry: usize,
+    col_num: usize,
+    col_var_index: usize,
+    col_misc: usize,
+    col_flags: usize,
+    col_fixed_offset: usize,
+    col_len: usize,
+}
+
+const JET3_LAYOUT: Layout = Layout {
+    page_size: 2048,
+    data_row_count: 0x08,
+    tdef_num_cols: 0x19,
+    tdef_num_real_idx: 0x1f,
+    tdef_used_pages: 0x23,
+    tdef_real_idx_start: 0x2b,
+    real_idx_entry: 8,
+    col_entry: 18,
+    col_num: 1,
+    col_var_index: 3,
+    col_misc: 9,
+    col_flags: 13,
+    col_fixed_offset: 14,
+    col_len: 16,
+};
+
+const JET4_LAYOUT: Layout = Layout {
+    page_size: 4096,
+    data_row_count: 0x0c,
+    tdef_num_cols: 0x2d,
+    tdef_num_real_idx: 0x33,
+    tdef_used_pages: 0x37,
+    tdef_real_idx_start: 0x3f,
+    real_idx_entry: 12,
+    col_entry: 25,
+    col_num: 5,
+    col_var_index: 7,
+    col_misc: 11,
+    col_flags: 15,
+    col_fixed_offset: 21,
+    col_len: 23,
+};
+
+#[derive(Debug, Clone)]
+pub struct JetColumn {
+    pub name: String,
+    pub col_type: u8,
+    col_num: usize,
+    var_index: usize,
+    fixed_offset: usize,
+    len: usize,
+    fixed: bool,
+    scale: u8,
+}
+
+/// A decoded table. Values are rendered as text; `None` is SQL NULL.
+#[derive(Debug, Clone)]
+pub struct JetTable {
+    pub columns: Vec<JetColumn>,
+    pub rows: Vec<Vec<Option<String>>>,
+}
+
+impl JetTable {
+    pub fn column_names(&self) -> Vec<String> {
+        self.columns.iter().map(|c| c.name.clone()).collect()
+    }
+}
+
+struct TableDef {
+    columns: Vec<JetColumn>,
+    used_pages: u32,
+}
+
+pub struct JetFile {
+    data: Vec<u8>,
+    version: JetVersion,
+    layout: &'static Layout,
+}
+
+impl JetFile {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let data = std::fs::read(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        Self::from_bytes(data)
+    }
+
+    pub fn from_bytes(mut data: Vec<u8>) -> Result<Self> {
+        if data.len() < JET3_LAYOUT.page_size || &data[4..19] != b"Standard Jet DB" {
+            bail!("Not a Jet database (missing 'Standard Jet DB' signature)");
+        }
+        let (version, layout) = match data[0x14] {
+            0 => (JetVersion::Jet3, &JET3_LAYOUT),
+            1 => (JetVersion::Jet4, &JET4_LAYOUT),
+            v => bail!("Unsupported Jet version byte {:#04x}", v),
+        };
+        if !data.len().is_multiple_of(layout.page_size) {
+            bail!("File size {} is not a multiple of the {} byte page size", data.len(), layout.page_size);
+        }
+
+        // The header is obfuscated with a fixed RC4 key; it holds the page encryption key.
+        let header_len = if version == JetVersion::Jet3 { 126 } else { 128 };
+        let mut header = data[0x18..0x18 + header_len].to_vec();
+        rc4(&[0xc7, 0xda, 0x39, 0x6b], &mut header);
+        let db_key = u32::from_le_bytes([header[0x26], header[0x27], header[0x28], header[0x29]]);
+        if db_key != 0 {
+            for (page, buf) in data.chunks_mut(layout.page_size).enumerate().skip(1) {
+                rc4(&(db_key ^ page as u32).to_le_bytes(), buf);
+            }
+        }
+
+        Ok(Self { data, version, layout })
+    }
+
+    pub fn version(&self) -> JetVersion {
+        self.version
+    }
+
+    /// Names of the user tables, in catalog order.
+    pub fn table_names(&self) -> Result<Vec<String>> {
+        Ok(self.user_tables()?.into_iter().map(|(name, _)| name).collect())
+    }
+
+    pub fn read_table(&self, table_name: &str) -> Result<JetTable> {
+        let (_, page) = self.user_tables()?
+            .into_iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(table_name))
+            .with_context(|| format!("Table {} not found", table_name))?;
+        self.read_table_at(page)
+    }
+
+    fn user_tables(&self) -> Result<Vec<(String, u32)>> {
+        let catalog = self.read_table_at(MSYS_OBJECTS_PAGE).context("Failed to read MSysObjects")?;
+        let index_of = |name: &str| {
+            catalog.columns.iter().position(|c| c.name == name)
+                .with_context(|| format!("MSysObjects has no {} column", name))
+        };
+        let (id_col, name_col, type_col, flags_col) = (index_of("Id")?, index_of("Name")?, index_of("Type")?, index_of("Flags")?);
+        let as_int = |v: &Option<String>| v.as_deref().and_then(|s| s.parse::<i64>().ok()).unwrap_or(0);
+
+        Ok(catalog.rows.iter()
+            .filter(|row| as_int(&row[type_col]) & 0x7fff == OBJECT_TYPE_TABLE)
+            .filter(|row| as_int(&row[flags_col]) & SYSTEM_OBJECT_FLAGS == 0)
+            .filter_map(|row| {
+                let name = row[name_col].clone()?;
+                let page = (as_int(&row[id_col]) & 0x00ff_ffff) as u32;
+                Some((name, page))
+            })
+            .collect())
+    }
+
+    fn read_table_at(&self, tdef_page: u32) -> Result<JetTable> {
+        let tdef = self.read_tdef(tdef_page)?;
+        let mut rows = Vec::new();
+        for page in self.usage_map_pages(tdef.used_pages)? {
+            let buf = self.page(page)?;
+            if buf[0] != PAGE_TYPE_DATA || read_u32(buf, 4)? != tdef_page {
+                continue;
+            }
+            let num_rows = read_u16(buf, self.layout.data_row_count)? as usize;
+            for row in 0..num_rows {
+                let raw = read_u16(buf, self.layout.data_row_count + 2 + row * 2)?;
+                if raw & ROW_DELETED != 0 {
+                    continue;
+                }
+                let (mut row_page, mut start, mut end) = self.row_bounds(page, row)?;
+                if raw & ROW_LOOKUP != 0 {
+                    // Overflow row: the stored bytes point at the real row.
+                    let pointer = read_u32(buf, start)?;
+                    (row_page, start, end) = self.row_bounds(pointer >> 8, (pointer & 0xff) as usize)?;
+                }
+                let row_buf = &self.page(row_page)?[start..end];
+                rows.push(self.crack_row(&tdef.columns, row_buf)?);
+            }
+        }
+        Ok(JetTable { columns: tdef.columns, rows })
+    }
+
+    fn page(&self, page: u32) -> Result<&[u8]> {
+        let size = self.layout.page_size;
+        let start = page as usize * size;
+        self.data.get(start..start + size)
+            .with_context(|| format!("Page {} is past the end of the file", page))
+    }
+
+    /// Returns (page, start, end) of a row within its page.
+    fn row_bounds(&self, page: u32, row: usize) -> Result<(u32, usize, usize)> {
+        let buf = self.page(page)?;
+        let table = self.layout.data_row_count;
+        let num_rows = read_u16(buf, table)? as usize;
+        if row >= num_rows {
+            bail!("Row {} does not exist on page {}", row, page);
+        }
+        let start = (read_u16(buf, table + 2 + row * 2)? & ROW_OFFSET_MASK) as usize;
+        let end = if row == 0 {
+            self.layout.page_size
+        } else {
+            (read_u16(buf, table + row * 2)? & ROW_OFFSET_MASK) as usize
+        };
+        if start > end || end > self.layout.page_size {
+            bail!("Corrupt row {} on page {}", row, page);
+        }
+        Ok((page, start, end))
+    }
+
+    /// Concatenates a table definition, which may span several pages.
+    fn tdef_bytes(&self, first_page: u32) -> Result<Vec<u8>> {
+        let mut bytes = Vec::new();
+        let mut page = first_page;
+        loop {
+            let buf = self.page(page)?;
+            if buf[0] != PAGE_TYPE_TDEF {
+                bail!("Page {} is not a table definition", page);
+            }
+            // Continuation pages repeat an 8 byte page header that is not part of the definition.
+            if bytes.is_empty() {
+                bytes.extend_from_slice(buf);
+            } else {
+                bytes.extend_from_slice(&buf[8..]);
+            }
+            page = read_u32(buf, 4)?;
+            if page == 0 {
+                break;
+            }
+            if bytes.len() > self.data.len() {
+                bail!("Table definition chain starting at page {} loops", first_page);
+            }
+        }
+        Ok(bytes)
+    }
+
+    fn read_tdef(&self, page: u32) -> Result<TableDef> {
+        let l = self.layout;
+        let buf = self.tdef_bytes(page)?;
+        let num_cols = read_u16(&buf, l.tdef_num_cols)? as usize;
+        let num_real_idx = read_u32(&buf, l.tdef_num_real_idx)? as usize;
+        let used_pages = read_u32(&buf, l.tdef_used_pages)?;
+
+        let mut offset = num_real_idx.checked_mul(l.real_idx_entry)
+            .and_then(|len| len.checked_add(l.tdef_real_idx_start))
+            .with_context(|| format!("Corrupt index count in table at page {}", page))?;
+        let mut columns = Vec::with_capacity(num_cols);
+        for _ in 0..num_cols {
+            let entry = buf.get(offset..offset + l.col_entry)
+                .with_context(|| format!("Truncated column definitions in table at page {}", page))?;
+            columns.push(JetColumn {
+                name: String::new(),
+                col_type: entry[0],
+                col_num: read_u16(entry, l.col_num)? as usize,
+                var_index: read_u16(entry, l.col_var_index)? as usize,
+                fixed_offset: read_u16(entry, l.col_fixed_offset)? as usize,
+                len: read_u16(entry, l.col_len)? as usize,
+                fixed: entry[l.col_flags] & COL_FLAG_FIXED != 0,
+                scale: entry[l.col_misc + 1],
+            });
+            offset += l.col_entry;
+        }
+
+        for column in columns.iter_mut() {
+            let (name, consumed) = match self.version {
+                JetVersion::Jet3 => {
+                    let len = *buf.get(offset).context("Truncated column names")? as usize;
+                    let bytes = buf.get(offset + 1..offset + 1 + len).context("Truncated column names")?;
+                    (decode_cp1252(bytes), 1 + len)
+                }
+                JetVersion::Jet4 => {
+                    let len = read_u16(&buf, offset).context("Truncated column names")? as usize;
+                    let bytes = buf.get(offset + 2..offset + 2 + len).context("Truncated column names")?;
+                    (decode_jet4_text(bytes), 2 + len)
+                }
+            };
+            column.name = name;
+            offset += consumed;
+        }
+
+        columns.sort_by_key(|c| c.col_num);
+        Ok(TableDef { columns, used_pages })
+    }
+
+    /// Lists the pages flagged in a usage map, which is stored as a row elsewhere.
+    fn usage_map_pages(&self, pointer: u32) -> Result<Vec<u32>> {
+        let (page, start, end) = self.row_bounds(pointer >> 8, (pointer & 0xff) as usize)?;
+        let map = &self.page(page)?[start..end];
+        let mut pages = Vec::new();
+        match map.first() {
+            Some(0) => {
+                // Inline bitmap starting at a given page
+                let first = read_u32(map, 1).context("Truncated usage map")?;
+                collect_bits(map.get(5..).unwrap_or_default(), first, &mut pages);
+            }
+            Some(1) => {
+                // Bitmaps live on dedicated usage map pages
+                let bits_per_page = (self.layout.page_size - 4) * 8;
+                for (i, chunk) in map.get(1..).unwrap_or_default().chunks_exact(4).enumerate() {
+                    let map_page = u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+                    if map_page == 0 {
+                        continue;
+                    }
+                    let buf = self.page(map_page)?;
+                    if buf[0] != PAGE_TYPE_USAGE_MAP {
+                        bail!("Page {} is not a usage map", map_page);
+                    }
+                    collect_bits(&buf[4..], (i * bits_per_page) as u32, &mut pages);
+                }
+            }
+            other => bail!("Unknown usage map type {:?}", other),
+        }
+        Ok(pages)
+    }
+
+    fn crack_row(&self, columns: &[JetColumn], row: &[u8]) -> Result<Vec<Option<String>>> {
+        let jet4 = self.version == JetVersion::Jet4;
+        let count_size = if jet4 { 2 } else { 1 };
+        if row.len() < count_size {
+            bail!("Row too short");
+        }
+        let row_cols = if jet4 { read_u16(row, 0)? as usize } else { row[0] as usize };
+        let mask_len = row_cols.div_ceil(8);
+        let end = row.len();
+        if end < count_size + mask_len + count_size {
+            bail!("Row too short for {} columns", row_cols);
+        }
+        let null_mask = &row[end - mask_len..];
+
+        // Rows only carry a variable column table when the table has variable columns.
+        let var_offsets = if columns.iter().all(|c| c.fixed) {
+            vec![0]
+        } else if jet4 {
+            let var_cols = read_u16(row, end - mask_len - 2)? as usize;
+            (0..=var_cols)
+                .map(|i| {
+                    let pos = (end - mask_len - 2).checked_sub(2 * (i + 1)).context("Corrupt variable column table")?;
+                    Ok(read_u16(row, pos)? as usize)
+                })
+                .collect::<Result<Vec<_>>>()?
+        } else {
+            jet3_var_offsets(row, mask_len)?
+        };
+        let var_cols = var_offsets.len() - 1;
+
+        let is_set = |col_num: usize| {
+            col_num < row_cols && null_mask.get(col_num / 8).map(|b| b & (1 << (col_num % 8)) != 0).unwrap_or(false)
+        };
+
+        let mut values = Vec::with_capacity(columns.len());
+        for column in columns {
+            // Booleans are stored in the null mask itself.
+            if column.col_type == COL_BOOL {
+                values.push(Some(if is_set(column.col_num) { "1" } else { "0" }.to_string()));
+                continue;
+            }
+            if !is_set(column.col_num) {
+                values.push(None);
+                continue;
+            }
+            let bytes = if column.fixed {
+                let start = count_size + column.fixed_offset;
+                row.get(start..start + column.len)
+            } else if column.var_index < var_cols {
+                let start = var_offsets[column.var_index];
+                let stop = var_offsets[column.var_index + 1];
+                if start <= stop { row.get(start..stop) } else { None }
+            } else {
+                None
+            };
+            values.push(match bytes {
+                Some(bytes) => self.format_value(column, bytes)?,
+                None => None,
+            });
+        }
+        Ok(values)
+    }
+
+    fn format_value(&self, column: &JetColumn, bytes: &[u8]) -> Result<Option<String>> {
+        let value = match column.col_type {
+            COL_BYTE => bytes.first().context("Empty byte value")?.to_string(),
+            COL_INT => (read_u16(bytes, 0)? as i16).to_string(),
+            COL_LONG => (read_u32(bytes, 0)? as i32).to_string(),
+            COL_MONEY => format_scaled(i64::from_le_bytes(read_array(bytes)?) as i128, 4),
+            COL_FLOAT => format_float32(f32::from_le_bytes(read_array(bytes)?)),
+            COL_DOUBLE => format_float(f64::from_le_bytes(read_array(bytes)?)),
+            COL_DATETIME => format_datetime(f64::from_le_bytes(read_array(bytes)?))
+                .with_context(|| format!("Invalid date in column {}", column.name))?,
+            COL_TEXT => self.decode_text(bytes),
+            COL_MEMO => self.decode_text(&self.read_memo(bytes)?),
+            COL_BINARY => bytes.iter().map(|b| format!("{:02X}", b)).collect(),
+            COL_GUID => format_guid(bytes)?,
+            COL_NUMERIC => format_numeric(bytes, column.scale),
+            COL_OLE => return Ok(None),
+            _ => return Ok(None),
+        };
+        Ok(Some(value))
+    }
+
+    fn decode_text(&self, bytes: &[u8]) -> String {
+        match self.version {
+            JetVersion::Jet3 => decode_cp1252(bytes),
+            JetVersion::Jet4 => decode_jet4_text(bytes),
+        }
+    }
+
+    /// Resolves a memo field, which is either inline or stored in LVAL pages.
+    fn read_memo(&self, field: &[u8]) -> Result<Vec<u8>> {
+        if field.len() < MEMO_OVERHEAD {
+            bail!("Memo field too short");
+        }
+        let header = read_u32(field, 0)?;
+        let len = (header & 0x3fff_ffff) as usize;
+        if header & MEMO_INLINE != 0 {
+            return Ok(field[MEMO_OVERHEAD..].to_vec());
+        }
+
+        let mut pointer = read_u32(field, 4)?;
+        if header & MEMO_SINGLE_PAGE != 0 {
+            let (page, start, end) = self.row_bounds(pointer >> 8, (pointer & 0xff) as usize)?;
+            let data = &self.page(page)?[start..end];
+            return Ok(data[..len.min(data.len())].to_vec());
+        }
+
+        let mut data = Vec::with_capacity(len.min(self.data.len()));
+        let mut chunks = 0;
+        while pointer != 0 && data.len() < len {
+            let (page, start, end) = self.row_bounds(pointer >> 8, (pointer & 0xff) as usize)?;
+            let chunk = &self.page(page)?[start..end];
+            chunks += 1;
+            if chunk.len() < 4 || chunks * self.layout.page_size > self.data.len() {
+                bail!("Corrupt memo chain");
+            }
+            pointer = read_u32(chunk, 0)?;
+            data.extend_from_slice(&chunk[4..]);
+        }
+        data.truncate(len);
+        Ok(data)
+    }
+}
+
+fn jet3_var_offsets(row: &[u8], mask_len: usize) -> Result<Vec<usize>> {
+    let byte_at = |pos: usize| row.get(pos).copied().context("Corrupt variable column table");
+    let end = row.len().checked_sub(1 + mask_len).context("Corrupt variable column table")? + mask_len;
+    let var_cols = byte_at(end - mask_len)? as usize;
+    let mut num_jumps = end / 256;
+    let col_ptr = (end - mask_len)
+        .checked_sub(num_jumps + 1)
+        .context("Corrupt variable column table")?;
+    if col_ptr.saturating_sub(var_cols) / 256 < num_jumps {
+        num_jumps -= 1;
+    }
+    let mut jumps_used = 0;
+    let mut offsets = Vec::with_capacity(var_cols + 1);
+    for i in 0..=var_cols {
+        while jumps_used < num_jumps && i == byte_at(end - mask_len - jumps_used - 1)? as usize {
+            jumps_used += 1;
+        }
+        let pos = col_ptr.checked_sub(i).context("Corrupt variable column table")?;
+        offsets.push(byte_at(pos)? as usize + jumps_used * 256);
+    }
+    Ok(offsets)
+}
+
+fn collect_bits(bitmap: &[u8], first_page: u32, pages: &mut Vec<u32>) {
+    for (byte_idx, byte) in bitmap.iter().enumerate() {
+        for bit in 0..8 {
+            if byte & (1 << bit) != 0 {
+                // A corrupt start page can't wrap around to low pages
+                if let Some(page) = u32::try_from(byte_idx * 8 + bit).ok().and_then(|i| first_page.checked_add(i)) {
+                    pages.push(page);
+                }
+            }
+        }
+    }
+}
+
+fn read_u16(buf: &[u8], offset: usize) -> Result<u16> {
+    Ok(u16::from_le_bytes(read_array(buf.get(offset..).unwrap_or_default())?))
+}
+
+fn read_u32(buf: &[u8], offset: usize) -> Result<u32> {
+    Ok(u32::from_le_bytes(read_array(buf.get(offset..).unwrap_or_default())?))
+}
+
+/// The first `N` bytes of `buf`, or an error if it is shorter.
+fn read_array<const N: usize>(buf: &[u8]) -> Result<[u8; N]> {
+    buf.get(..N)
+        .and_then(|bytes| bytes.try_into().ok())
+        .with_context(|| format!("Value truncated: expected {} bytes, found {}", N, buf.len()))
+}
+
+fn rc4(key: &[u8], data: &mut [u8]) {
+    let mut s: Vec<u8> = (0..=255).collect();
+    let mut j: u8 = 0;
+    for i in 0..256 {
+        j = j.wrapping_add(s[i]).wrapping_add(key[i % key.len()]);
+        s.swap(i, j as usize);
+    }
+    let (mut i, mut j) = (0u8, 0u8);
+    for byte in data.iter_mut() {
+        i = i.wrapping_add(1);
+        j = j.wrapping_add(s[i as usize]);
+        s.swap(i as usize, j as usize);
+        *byte ^= s[s[i as usize].wrapping_add(s[j as usize]) as usize];
+    }
+}
+
+/// Jet4 text is UCS-2, optionally "compressed": a 0xFF 0xFE prefix followed by
+/// single byte characters, with 0x00 toggling between one and two byte runs.
+fn decode_jet4_text(bytes: &[u8]) -> String {
+    if bytes.len() >= 2 && bytes[0] == 0xff && bytes[1] == 0xfe {
+        let mut units = Vec::with_capacity(bytes.len());
+        let mut compressed = true;
+        let mut i = 2;
+        while i < bytes.len() {
+            if bytes[i] == 0 {
+                compressed = !compressed;
+                i += 1;
+            } else if compressed {
+                units.push(bytes[i] as u16);
+                i += 1;
+            } else if i + 1 < bytes.len() {
+                units.push(u16::from_le_bytes([bytes[i], bytes[i + 1]]));
+                i += 2;
+            } else {
+                break;
+            }
+        }
+        String::from_utf16_lossy(&units)
+    } else {
+        let units: Vec<u16> = bytes.chunks_exact(2).map(|c| u16::from_le_bytes([c[0], c[1]])).collect();
+        String::from_utf16_lossy(&units)
+    }
+}
+
+const CP1252_HIGH: [char; 32] = [
+    '\u{20ac}', '\u{fffd}', '\u{201a}', '\u{0192}', '\u{201e}', '\u{2026}', '\u{2020}', '\u{2021}',
+    '\u{02c6}', '\u{2030}', '\u{0160}', '\u{2039}', '\u{0152}', '\u{fffd}', '\u{017d}', '\u{fffd}',
+    '\u{fffd}', '\u{2018}', '\u{2019}', '\u{201c}', '\u{201d}', '\u{2022}', '\u{2013}', '\u{2014}',
+    '\u{02dc}', '\u{2122}', '\u{0161}', '\u{203a}', '\u{0153}', '\u{fffd}', '\u{017e}', '\u{0178}',
+];
+
+fn decode_cp1252(bytes: &[u8]) -> String {
+    bytes.iter()
+        .map(|&b| match b {
+            0x80..=0x9f => CP1252_HIGH[(b - 0x80) as usize],
+            _ => b as char,
+        })
+        .collect()
+}
+
+fn format_float(value: f64) -> String {
+    if value.is_finite() && value.fract() == 0.0 && value.abs() < 1e15 {
+        format!("{}", value as i64)
+    } else {
+        format!("{}", value)
+    }
+}
+
+fn format_float32(value: f32) -> String {
+    if value.is_finite() && value.fract() == 0.0 && value.abs() < 1e7 {
+        format!("{}", value as i64)
+    } else {
+        format!("{}", value)
+    }
+}
+
+fn format_scaled(value: i128, scale: u32) -> String {
+    if scale == 0 {
+        return value.to_string();
+    }
+    let divisor = 10u128.pow(scale.min(38));
+    let sign = if value < 0 { "-" } else { "" };
+    let abs = value.unsigned_abs();
+    format!("{}{}.{:0width$}", sign, abs / divisor, abs % divisor, width = scale as usize)
+}
+
+fn format_numeric(bytes: &[u8], scale: u8) -> String {
+    if bytes.len() < 17 {
+        return String::new();
+    }
+    // 16 byte magnitude stored as four little endian words, most significant first.
+    let mut magnitude: i128 = 0;
+    for word in bytes[1..17].chunks_exact(4) {
+        magnitude = (magnitude << 32) | u32::from_le_bytes([word[0], word[1], word[2], word[3]]) as i128;
+    }
+    let value = if bytes[0] & 0x80 != 0 { magnitude.wrapping_neg() } else { magnitude };
+    format_scaled(value, scale as u32)
+}
+
+/// Dates are days since 1899-12-30; printed like mdb-export's default `%m/%d/%y %H:%M:%S`.
+fn format_datetime(days: f64) -> Result<String> {
+    let epoch = NaiveDate::from_ymd_opt(1899, 12, 30).unwrap().and_hms_opt(0, 0, 0).unwrap();
+    if !days.is_finite() {
+        bail!("Date value {} is not a number", days);
+    }
+    let whole = days.trunc();
+    let seconds = (days.fract().abs() * 86_400.0).round() as i64;
+    let dt = TimeDelta::try_days(whole as i64)
+        .zip(TimeDelta::try_seconds(seconds))
+        .and_then(|(days, seconds)| epoch.checked_add_signed(days)?.checked_add_signed(seconds))
+        .with_context(|| format!("Date value {} is out of range", days))?;
+    Ok(dt.format("%m/%d/%y %H:%M:%S").to_string())
+}
+
+fn format_guid(bytes: &[u8]) -> Result<String> {
+    if bytes.len() < 16 {
+        return Ok(String::new());
+    }
+    Ok(format!(
+        "{{{:08X}-{:04X}-{:04X}-{}-{}}}",
+        read_u32(bytes, 0)?,
+        read_u16(bytes, 4)?,
+        read_u16(bytes, 6)?,
+        bytes[8..10].iter().map(|b| format!("{:02X}", b)).collect::<String>(),
+        bytes[10..16].iter().map(|b| format!("{:02X}", b)).collect::<String>(),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+    use std::process::Command;
+
+    /// The tables the export reads
+    const EXPORT_TABLES: [&str; 9] = [
+        "TCompetition", "TPatineurs", "TPatineur_compe", "TClubs", "TRegions",
+        "TDistances_Standards", "TProg_Courses", "TVagues", "TPatVagues",
+    ];
+
+    fn test_data() -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("test-data")
+    }
+
+    fn pat_files() -> Vec<PathBuf> {
+        let mut files: Vec<PathBuf> = std::fs::read_dir(test_data()).unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("pat")))
+            .collect();
+        files.sort();
+        assert!(!files.is_empty(), "no .pat files in test-data");
+        files
+    }
+
+    fn rows_as_text(table: &JetTable) -> Vec<Vec<String>> {
+        table.rows.iter()
+            .map(|row| row.iter().map(|v| v.clone().unwrap_or_default()).collect())
+            .collect()
+    }
+
+    fn column(table: &JetTable, name: &str) -> usize {
+        table.columns.iter().position(|c| c.name == name).unwrap()
+    }
+
+    #[test]
+    fn matches_mdb_export() {
+        if Command::new("mdb-export").arg("--version").output().is_err() {
+            eprintln!("mdb-export is not installed, skipping the comparison");
+            return;
+        }
+        for path in pat_files() {
+            let jet = JetFile::open(&path).unwrap();
+            for name in jet.table_names().unwrap() {
+                let output = Command::new("mdb-export").arg(&path).arg(&name).output().unwrap();
+                assert!(output.status.success(), "mdb-export failed on {}", name);
+                let mut reader = csv::ReaderBuilder::new().has_headers(false).from_reader(output.stdout.as_slice());
+                let mut expected = reader.records().map(|r| r.unwrap().iter().map(str::to_string).collect::<Vec<_>>());
+                let table = jet.read_table(&name).unwrap();
+                assert_eq!(expected.next().unwrap(), table.column_names(), "columns of {}", name);
+                assert_eq!(expected.collect::<Vec<_>>(), rows_as_text(&table), "rows of {} in {}", name, path.display());
+            }
+        }
+    }
+
+    #[test]
+    fn reads_skaters_from_baseline_export() {
+        // test-data/LYNX.EVT was written through mdb-export; every skater in it must read the same.
+        let evt = std::fs::read_to_string(test_data().join("LYNX.EVT")).unwrap();
+        let jet = JetFile::open(&pat_files()[0]).unwrap();
+        assert_eq!(jet.version(), JetVersion::Jet4);
+        let skaters = jet.read_table("TPatineurs").unwrap();
+        let (last, first, code) = (column(&skaters, "Nom"), column(&skaters, "Prenom"), column(&skaters, "CodePat"));
+
+        let mut checked = 0;
+        for line in evt.lines().filter(|l| l.starts_with(',')) {
+            let fields: Vec<&str> = line.split(',').collect();
+            let found = skaters.rows.iter().any(|row| {
+                row[code].as_deref() == Some(fields[7])
+                    && row[last].as_deref().map(str::trim) == Some(fields[3])
+                    && row[first].as_deref().map(str::trim) == Some(fields[4])
+            });
+            assert!(found, "skater missing or different: {}", line);
+            checked += 1;
+        }
+        assert_eq!(checked, 441);
+    }
+
+    #[test]
+    fn reads_every_table() {
+        for path in pat_files() {
+            let jet = JetFile::open(&path).unwrap();
+            for name in jet.table_names().unwrap() {
+                if let Err(e) = jet.read_table(&name) {
+                    panic!("{} in {}: {:#}", name, path.display(), e);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn lists_gcpv_tables() {
+        let names = JetFile::open(&pat_files()[0]).unwrap().table_names().unwrap();
+        for table in EXPORT_TABLES {
+            assert!(names.iter().any(|n| n == table), "{} not in {:?}", table, names);
+        }
+    }
+
+    #[test]
+    fn formats_dates_like_mdb_export() {
+        assert_eq!(format_datetime(45619.0).unwrap(), "11/23/24 00:00:00");
+        assert_eq!(format_datetime(45619.75).unwrap(), "11/23/24 18:00:00");
+        assert_eq!(format_datetime(0.0).unwrap(), "12/30/99 00:00:00");
+        assert!(format_datetime(1e300).is_err());
+        assert!(format_datetime(-1e18).is_err());
+        assert!(format_datetime(f64::NAN).is_err());
+    }
+
+    #[test]
+    fn short_values_are_errors() {
+        assert!(read_u16(&[1], 0).is_err());
+        assert!(read_u32(&[1, 2, 3, 4], 1).is_err());
+        assert_eq!(read_u32(&[1, 0, 0, 0, 9], 0).unwrap(), 1);
+    }
+
+    #[test]
+    fn truncated_file_is_an_error() {
+        let data = std::fs::read(&pat_files()[0]).unwrap();
+        assert!(JetFile::from_bytes(data[..100].to_vec()).is_err());
+        assert!(JetFile::from_bytes(data[..data.len() - 1].to_vec()).is_err());
+
+        // Whole pages missing from the end: tables pointing past it fail instead of panicking
+        let jet = JetFile::from_bytes(data[..JET4_LAYOUT.page_size * 16].to_vec()).unwrap();
+        assert!(jet.table_names().is_err() || ["TPatineurs", "TPatVagues"].iter().any(|t| jet.read_table(t).is_err()));
+    }
+
+    #[test]
+    fn corrupt_pages_do_not_panic() {
+        // Corrupt the decrypted pages, so the scribbles land on the structures being parsed
+        let clean = JetFile::open(&pat_files()[0]).unwrap();
+        let tables: Vec<u32> = clean.user_tables().unwrap().into_iter()
+            .filter(|(name, _)| EXPORT_TABLES.contains(&name.as_str()))
+            .map(|(_, page)| page)
+            .collect();
+        let page_size = clean.layout.page_size;
+        let pages = clean.data.len() / page_size;
+
+        // Deterministic xorshift, so a failure can be reproduced
+        let mut seed: u64 = 0x9e37_79b9_7f4a_7c15;
+        let mut next = move || {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            seed
+        };
+        let mut errors = 0;
+        for _ in 0..300 {
+            let mut data = clean.data.clone();
+            // Scribble over a few bytes of a few pages, sometimes a whole page
+            for _ in 0..4 {
+                let page = 1 + next() as usize % (pages - 1);
+                if next() % 4 == 0 {
+                    for byte in &mut data[page * page_size..(page + 1) * page_size] {
+                        *byte = next() as u8;
+                    }
+                } else {
+                    for _ in 0..8 {
+                        let offset = page * page_size + next() as usize % page_size;
+                        data[offset] = next() as u8;
+                    }
+                }
+            }
+            let jet = JetFile { data, version: clean.version, layout: clean.layout };
+            if jet.table_names().is_err() {
+                errors += 1;
+            }
+            for &table in &tables {
+                if jet.read_table_at(table).is_err() {
+                    errors += 1;
+                }
+            }
+        }
+        assert!(errors > 0, "corruption was never detected");
+    }
+}
@@ -1,5 +1,19 @@
 pub mod db;
+pub mod jet;
+pub mod source;
 pub mod logic;
 pub mod writer;
+pub mod race_time;
+pub mod round;
+pub mod logos;
+pub mod text;
+pub mod lif;
+pub mod write_back;
 pub mod app_logic;
+pub mod change;
+pub mod file_copy;
+pub mod watcher;
+pub mod settings;
 pub mod gui;
+pub mod web;
+pub mod cli;
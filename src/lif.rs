@@ -0,0 +1,370 @@
+use crate::logic::{CompetitorInCompetition, Lane, Race};
+use crate::race_time::RaceTime;
+use crate::writer::lynx_event_id;
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::path::Path;
+use std::time::SystemTime;
+
+/// First line of a .LIF file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LifHeader {
+    pub event: String,
+    pub round: i32,
+    pub heat: i32,
+    pub name: String,
+}
+
+/// One competitor line of a .LIF file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LifRow {
+    pub place: Option<i32>,
+    pub helmet_id: Option<i32>,
+    pub lane: Option<i32>,
+    pub last_name: String,
+    pub first_name: String,
+    pub affiliation: String,
+    /// Includes DNS/DNF/DQ, whichever column Lynx put them in
+    pub time: Option<RaceTime>,
+    pub license: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct LifFile {
+    pub header: LifHeader,
+    pub rows: Vec<LifRow>,
+}
+
+impl LifFile {
+    pub fn read<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let data = std::fs::read(path).with_context(|| format!("Failed to read {}", path.display()))?;
+        // Lynx writes in the system code page; names are only informational here
+        parse_lif(&String::from_utf8_lossy(&data))
+            .with_context(|| format!("Failed to parse {}", path.display()))
+    }
+
+    /// Same (event, round, heat) as `writer::lynx_event_id`.
+    pub fn event_id(&self) -> (String, i32, i32) {
+        (self.header.event.clone(), self.header.round, self.header.heat)
+    }
+}
+
+pub fn parse_lif(contents: &str) -> Result<LifFile> {
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .from_reader(contents.as_bytes());
+    let mut records = reader.records();
+
+    let first = records.next().context("Empty .LIF file")??;
+    let field = |i: usize| first.get(i).unwrap_or("").trim();
+    let header = LifHeader {
+        event: field(0).to_string(),
+        round: parse_int(field(1)).unwrap_or(1),
+        heat: parse_int(field(2)).unwrap_or(1),
+        name: field(3).to_string(),
+    };
+    if header.event.is_empty() {
+        anyhow::bail!("Missing event number in .LIF header");
+    }
+
+    let mut rows = Vec::new();
+    for record in records {
+        let record = record?;
+        let field = |i: usize| record.get(i).unwrap_or("").trim();
+        if record.iter().all(|f| f.trim().is_empty()) {
+            continue;
+        }
+
+        let time = RaceTime::parse(field(6));
+        // Lynx puts DNS/DNF/DQ in the place column, older versions in the time column
+        let time = match (RaceTime::parse(field(0)).and_then(|t| t.code()), time) {
+            (Some(code), Some(time)) => Some(time.with_code(code)),
+            (Some(code), None) => Some(RaceTime::from_code(code)),
+            (None, time) => time,
+        };
+        let counts = time.is_none_or(|t| t.code().is_none());
+
+        rows.push(LifRow {
+            place: parse_int(field(0)).filter(|_| counts),
+            helmet_id: parse_int(field(1)),
+            lane: parse_int(field(2)),
+            last_name: field(3).to_string(),
+            first_name: field(4).to_string(),
+            affiliation: field(5).to_string(),
+            time,
+            license: field(7).to_string(),
+        });
+    }
+
+    Ok(LifFile { header, rows })
+}
+
+fn parse_int(s: &str) -> Option<i32> {
+    s.trim().parse().ok()
+}
+
+/// Reads every .LIF file in `dir`. When a heat was re-run, only the most
+/// recently written file for that event/round/heat is kept.
+pub fn read_lif_dir<P: AsRef<Path>>(dir: P) -> Result<Vec<LifFile>> {
+    let dir = dir.as_ref();
+    let entries = std::fs::read_dir(dir)
+        .with_context(|| format!("Failed to read results folder {}", dir.display()))?;
+
+    let mut latest: HashMap<(String, i32, i32), (SystemTime, LifFile)> = HashMap::new();
+    for entry in entries {
+        let path = entry?.path();
+        let is_lif = path.extension()
+            .and_then(|e| e.to_str())
+            .is_some_and(|e| e.eq_ignore_ascii_case("lif"));
+        if !is_lif {
+            continue;
+        }
+        // Lynx may still be writing it; pick it up next cycle
+        let lif = match LifFile::read(&path) {
+            Ok(lif) => lif,
+            Err(e) => {
+                eprintln!("Skipping {}: {:#}", path.display(), e);
+                continue;
+            }
+        };
+        let modified = std::fs::metadata(&path).and_then(|m| m.modified()).unwrap_or(SystemTime::UNIX_EPOCH);
+        let key = normalized_event_id(lif.event_id());
+        if latest.get(&key).is_none_or(|(seen, _)| modified >= *seen) {
+            latest.insert(key, (modified, lif));
+        }
+    }
+
+    let mut files: Vec<LifFile> = latest.into_values().map(|(_, lif)| lif).collect();
+    files.sort_by_key(|f| f.event_id());
+    Ok(files)
+}
+
+/// A .LIF row matched to a lane of a race.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LaneResult {
+    pub race_id: i32,
+    pub lane_id: i32,
+    pub place: Option<i32>,
+    pub time: Option<RaceTime>,
+}
+
+/// Results for a competition, looked up by lane.
+#[derive(Debug, Clone, Default)]
+pub struct RaceResults {
+    by_lane: HashMap<i32, LaneResult>,
+}
+
+impl RaceResults {
+    /// Matches .LIF files to races by event/round/heat, and rows to lanes by
+    /// helmet number, or by start position for rows without one.
+    pub fn build(
+        files: &[LifFile],
+        races: &[Race],
+        lanes: &[Lane],
+        competitors_in_comp: &[CompetitorInCompetition]
+    ) -> Self {
+        let race_map: HashMap<(String, i32, i32), &Race> = races.iter()
+            .map(|r| (normalized_event_id(lynx_event_id(r)), r))
+            .collect();
+        let helmet_map: HashMap<i32, i32> = competitors_in_comp.iter()
+            .filter_map(|c| c.helmet_id.map(|h| (c.id, h)))
+            .collect();
+
+        let mut by_lane = HashMap::new();
+        for file in files {
+            let race = match race_map.get(&normalized_event_id(file.event_id())) {
+                Some(race) => race,
+                None => {
+                    eprintln!("No race for .LIF event {} round {} heat {}", file.header.event, file.header.round, file.header.heat);
+                    continue;
+                }
+            };
+            let race_lanes: Vec<&Lane> = lanes.iter().filter(|l| l.race_id == race.id).collect();
+
+            for row in &file.rows {
+                // A helmet that isn't in this heat is a different skater, not a lane to guess
+                let lane = match row.helmet_id {
+                    Some(helmet) => race_lanes.iter().find(|l| helmet_map.get(&l.skater_in_competition_id) == Some(&helmet)),
+                    None => row.lane.and_then(|lane| race_lanes.iter().find(|l| l.start_position == Some(lane))),
+                };
+                let Some(lane) = lane else {
+                    eprintln!("No lane for helmet {:?} in race {}", row.helmet_id, race.name);
+                    continue;
+                };
+
+                // A later row for the same skater replaces an earlier one
+                by_lane.insert(lane.id, LaneResult {
+                    race_id: race.id,
+                    lane_id: lane.id,
+                    place: row.place,
+                    time: row.time,
+                });
+            }
+        }
+
+        Self { by_lane }
+    }
+
+    pub fn for_lane(&self, lane_id: i32) -> Option<&LaneResult> {
+        self.by_lane.get(&lane_id)
+    }
+
+    pub fn has_results(&self, race_id: i32) -> bool {
+        self.by_lane.values().any(|r| r.race_id == race_id)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.by_lane.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.by_lane.len()
+    }
+}
+
+fn normalized_event_id((event, round, heat): (String, i32, i32)) -> (String, i32, i32) {
+    (event.trim().to_uppercase(), round, heat)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::race_time::TimeCode;
+    use crate::round::Round;
+    use std::time::Duration;
+
+    const HEAT: &str = "\
+3A,1,1,500m Open Heat 3A
+1,11,2,KU,Ben,RICH,44.11,
+2,12,1,COTE,Emilie,MTL,45.234,
+DNF,21,3,HAAHEIM,Sam,CAL,,
+,22,4,LI,Ada,CAL,DQ,
+DNS,23,5,LIU,Jo,VAN,,
+";
+
+    fn race() -> Race {
+        Race {
+            id: 30,
+            name: "3A".to_string(),
+            distance: Some(500),
+            track: 111,
+            program_item_id: 11,
+            sequence: None,
+            round: Round::Heat,
+            program_order: None,
+        }
+    }
+
+    fn lane(id: i32, skater: i32, start_position: i32) -> Lane {
+        Lane {
+            id,
+            race_id: 30,
+            skater_in_competition_id: skater,
+            skater_upid: None,
+            time: None,
+            position: None,
+            start_position: Some(start_position),
+            lynx_result: None,
+        }
+    }
+
+    fn skater(id: i32, helmet_id: i32) -> CompetitorInCompetition {
+        CompetitorInCompetition {
+            id,
+            competitor_id: None,
+            club_id: None,
+            affiliation: None,
+            club_name: None,
+            region: None,
+            rank: None,
+            removed: None,
+            group: None,
+            helmet_id: Some(helmet_id),
+        }
+    }
+
+    fn temp_dir(name: &str) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("gcpv-lif-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn parses_header_and_times() {
+        let lif = parse_lif(HEAT).unwrap();
+        assert_eq!(lif.header, LifHeader { event: "3A".into(), round: 1, heat: 1, name: "500m Open Heat 3A".into() });
+        assert_eq!(lif.rows.len(), 5);
+
+        let first = &lif.rows[0];
+        assert_eq!((first.place, first.helmet_id, first.lane), (Some(1), Some(11), Some(2)));
+        assert_eq!(first.time, Some(RaceTime::from_millis(44_110)));
+        assert_eq!(lif.rows[1].time, Some(RaceTime::from_millis(45_234)));
+    }
+
+    #[test]
+    fn keeps_dns_dnf_dq_rows_without_place() {
+        let lif = parse_lif(HEAT).unwrap();
+        let codes: Vec<_> = lif.rows[2..].iter().map(|r| (r.place, r.time.and_then(|t| t.code()))).collect();
+        assert_eq!(codes, vec![
+            (None, Some(TimeCode::Dnf)),
+            (None, Some(TimeCode::Dq)),
+            (None, Some(TimeCode::Dns)),
+        ]);
+    }
+
+    #[test]
+    fn rejects_missing_event() {
+        assert!(parse_lif("").is_err());
+        assert!(parse_lif(",1,1,No event\n").is_err());
+    }
+
+    #[test]
+    fn matches_by_helmet_then_lane() {
+        // Lynx has no helmet for the first row, and the lanes in the file don't follow GCPV's
+        let lif = parse_lif("3A,1,1,\n1,,2,KU,Ben,,44.11,\n2,12,5,COTE,Emilie,,45.23,\n3,99,1,NOBODY,,,46.00,\n").unwrap();
+        let lanes = [lane(1, 101, 2), lane(2, 102, 1), lane(3, 103, 3)];
+        let skaters = [skater(101, 11), skater(102, 12), skater(103, 13)];
+        let results = RaceResults::build(&[lif], &[race()], &lanes, &skaters);
+
+        assert_eq!(results.len(), 2);
+        assert_eq!(results.for_lane(1).unwrap().time, Some(RaceTime::from_millis(44_110)));
+        assert_eq!(results.for_lane(2).unwrap().place, Some(2));
+        // Helmet 99 isn't in the heat, so lane 1 isn't guessed
+        assert!(results.for_lane(3).is_none());
+        assert!(results.has_results(30));
+    }
+
+    #[test]
+    fn ignores_files_for_other_races() {
+        let lif = parse_lif("3A,4,1,\n1,11,1,KU,Ben,,44.11,\n").unwrap();
+        let results = RaceResults::build(&[lif], &[race()], &[lane(1, 101, 1)], &[skater(101, 11)]);
+        assert!(results.is_empty());
+    }
+
+    #[test]
+    fn newest_file_wins_for_a_rerun_heat() {
+        let dir = temp_dir("rerun");
+        std::fs::write(dir.join("first.lif"), "3a,1,1,\n1,11,1,KU,Ben,,44.11,\n").unwrap();
+        std::fs::write(dir.join("rerun.LIF"), "3A,1,1,\n1,11,1,KU,Ben,,43.50,\n").unwrap();
+        std::fs::write(dir.join("notes.txt"), "not a result").unwrap();
+        std::fs::write(dir.join("broken.lif"), "").unwrap();
+
+        // The re-run sorts first by name, but was written last
+        let earlier = SystemTime::now() - Duration::from_secs(60);
+        std::fs::File::options().write(true).open(dir.join("first.lif")).unwrap().set_modified(earlier).unwrap();
+        let files = read_lif_dir(&dir).unwrap();
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].rows[0].time, Some(RaceTime::from_millis(43_500)));
+
+        std::fs::File::options().write(true).open(dir.join("rerun.LIF")).unwrap().set_modified(earlier - Duration::from_secs(60)).unwrap();
+        let files = read_lif_dir(&dir).unwrap();
+        assert_eq!(files[0].rows[0].time, Some(RaceTime::from_millis(44_110)));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,20 +1,109 @@
 use crate::db::*;
+use crate::lif::LaneResult;
+use crate::round::Round;
+use crate::source::DataSource;
 use anyhow::{Context, Result};
-use std::collections::HashMap;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+
+// Snapshot of every table the exporter uses, so each one is read once per cycle
+#[derive(Debug, Clone, Serialize)]
+pub struct Snapshot {
+    pub competitions: Vec<TCompetition>,
+    pub patineurs: Vec<TPatineurs>,
+    pub patineur_compe: Vec<TPatineurCompe>,
+    pub clubs: Vec<TClubs>,
+    pub regions: Vec<TRegions>,
+    pub distances: Vec<TDistancesStandards>,
+    pub prog_courses: Vec<TProgCourses>,
+    pub vagues: Vec<TVagues>,
+    pub pat_vagues: Vec<TPatVagues>,
+}
+
+impl Snapshot {
+    pub fn load<S: DataSource>(source: &S) -> Result<Self> {
+        Ok(Self {
+            competitions: source.read_table("TCompetition").context("Failed to read TCompetition")?,
+            patineurs: source.read_table("TPatineurs").context("Failed to read TPatineurs")?,
+            patineur_compe: source.read_table("TPatineur_compe").context("Failed to read TPatineur_compe")?,
+            clubs: source.read_table("TClubs").context("Failed to read TClubs")?,
+            // Only used for logos, so older files and fixtures without it still export
+            regions: source.read_table("TRegions").unwrap_or_else(|e| {
+                eprintln!("Could not read TRegions: {:#}", e);
+                Vec::new()
+            }),
+            distances: source.read_table("TDistances_Standards").context("Failed to read TDistances_Standards")?,
+            prog_courses: source.read_table("TProg_Courses").context("Failed to read TProg_Courses")?,
+            vagues: source.read_table("TVagues").context("Failed to read TVagues")?,
+            pat_vagues: source.read_table("TPatVagues").context("Failed to read TPatVagues")?,
+        })
+    }
+}
 
 // Competition Logic
-pub fn get_competition_id(file_path: &str) -> Result<i32> {
-    let competitions: Vec<TCompetition> = read_table(file_path, "TCompetition")?;
+pub fn get_competition_id<S: DataSource>(source: &S) -> Result<i32> {
+    let competitions: Vec<TCompetition> = source.read_table("TCompetition")?;
+    competition_id_from(&competitions)
+}
+
+pub fn competition_id_from(competitions: &[TCompetition]) -> Result<i32> {
     if competitions.is_empty() {
         anyhow::bail!("No competition found in mdb");
     }
     if competitions.len() > 1 {
-        anyhow::bail!("Multiple competitions found in mdb");
+        anyhow::bail!("Multiple competitions found in mdb, select one");
     }
     // Unwrap safely
     competitions[0].no_competition.context("Competition ID is missing or invalid")
 }
 
+#[derive(Debug, Clone, PartialEq)]
+pub struct CompetitionInfo {
+    pub id: i32,
+    pub location: Option<String>,
+    pub date: Option<String>,
+    pub club_id: Option<i32>,
+    pub club_name: Option<String>,
+}
+
+impl CompetitionInfo {
+    /// e.g. "4 - BC Cup 2024, 11/23/24 (Richmond)"
+    pub fn label(&self) -> String {
+        let mut label = format!("{} - {}", self.id, self.location.as_deref().unwrap_or("").trim());
+        if let Some(date) = &self.date {
+            // Access dates come with a midnight time attached
+            label.push_str(&format!(", {}", date.trim_end_matches(" 00:00:00")));
+        }
+        if let Some(club) = &self.club_name {
+            label.push_str(&format!(" ({})", club.trim()));
+        }
+        label
+    }
+}
+
+pub fn list_competitions<S: DataSource>(source: &S) -> Result<Vec<CompetitionInfo>> {
+    let competitions: Vec<TCompetition> = source.read_table("TCompetition")?;
+    let clubs: Vec<TClubs> = source.read_table("TClubs")?;
+    Ok(build_competitions(&competitions, &clubs))
+}
+
+pub fn build_competitions(competitions: &[TCompetition], clubs: &[TClubs]) -> Vec<CompetitionInfo> {
+    let club_map: HashMap<i32, &TClubs> = clubs.iter().filter_map(|c| c.no_club.map(|id| (id, c))).collect();
+
+    competitions.iter()
+        .filter_map(|c| {
+            let club = c.no_club.and_then(|id| club_map.get(&id));
+            Some(CompetitionInfo {
+                id: c.no_competition?,
+                location: c.lieu.clone(),
+                date: c.date.clone(),
+                club_id: c.no_club,
+                club_name: club.and_then(|cl| cl.nom_du_club.clone()),
+            })
+        })
+        .collect()
+}
+
 // Competitor Logic
 #[derive(Debug, Clone)]
 pub struct Competitor {
@@ -29,23 +118,27 @@ pub struct Competitor {
     pub club_id: Option<i32>,
 }
 
-pub fn get_competitors(file_path: &str) -> Result<Vec<Competitor>> {
-    let raw_competitors: Vec<TPatineurs> = read_table(file_path, "TPatineurs")?;
-    Ok(raw_competitors.into_iter()
+pub fn get_competitors<S: DataSource>(source: &S) -> Result<Vec<Competitor>> {
+    let raw_competitors: Vec<TPatineurs> = source.read_table("TPatineurs")?;
+    Ok(build_competitors(&raw_competitors))
+}
+
+pub fn build_competitors(raw_competitors: &[TPatineurs]) -> Vec<Competitor> {
+    raw_competitors.iter()
         .filter_map(|c| {
             Some(Competitor {
-                id: c.code_pat,
+                id: c.code_pat.clone(),
                 no_patineur: c.no_patineur?, // critical
-                first_name: c.prenom,
-                last_name: c.nom,
-                birth_date: c.date_naissance,
-                sex: c.sexe,
-                division: c.division,
+                first_name: c.prenom.clone(),
+                last_name: c.nom.clone(),
+                birth_date: c.date_naissance.clone(),
+                sex: c.sexe.clone(),
+                division: c.division.clone(),
                 category_id: c.no_categorie,
                 club_id: c.no_club,
             })
         })
-        .collect())
+        .collect()
 }
 
 #[derive(Debug, Clone)]
@@ -55,21 +148,34 @@ pub struct CompetitorInCompetition {
     pub club_id: Option<i32>,
     pub affiliation: Option<String>,
     pub club_name: Option<String>,
+    /// Region abbreviation of the club, e.g. "BC"
+    pub region: Option<String>,
     pub rank: Option<i32>,
     pub removed: Option<bool>,
     pub group: Option<String>,
     pub helmet_id: Option<i32>,
 }
 
-pub fn get_competitors_in_competition(file_path: &str, competition_id: i32) -> Result<Vec<CompetitorInCompetition>> {
-    let competitors = get_competitors(file_path)?;
-    let clubs: Vec<TClubs> = read_table(file_path, "TClubs")?;
-    let raw_comps: Vec<TPatineurCompe> = read_table(file_path, "TPatineur_compe")?;
+pub fn get_competitors_in_competition<S: DataSource>(source: &S, competition_id: i32) -> Result<Vec<CompetitorInCompetition>> {
+    let competitors = get_competitors(source)?;
+    let clubs: Vec<TClubs> = source.read_table("TClubs")?;
+    let regions: Vec<TRegions> = source.read_table("TRegions").unwrap_or_default();
+    let raw_comps: Vec<TPatineurCompe> = source.read_table("TPatineur_compe")?;
+    Ok(build_competitors_in_competition(&competitors, &clubs, &regions, &raw_comps, competition_id))
+}
 
-    let comp_map: HashMap<i32, Competitor> = competitors.into_iter().map(|c| (c.no_patineur, c)).collect();
-    let club_map: HashMap<i32, TClubs> = clubs.into_iter().filter_map(|c| c.no_club.map(|id| (id, c))).collect();
+pub fn build_competitors_in_competition(
+    competitors: &[Competitor],
+    clubs: &[TClubs],
+    regions: &[TRegions],
+    raw_comps: &[TPatineurCompe],
+    competition_id: i32,
+) -> Vec<CompetitorInCompetition> {
+    let comp_map: HashMap<i32, &Competitor> = competitors.iter().map(|c| (c.no_patineur, c)).collect();
+    let club_map: HashMap<i32, &TClubs> = clubs.iter().filter_map(|c| c.no_club.map(|id| (id, c))).collect();
+    let region_map: HashMap<i32, &str> = regions.iter().filter_map(|r| Some((r.no_region?, r.region.as_deref()?))).collect();
 
-    Ok(raw_comps.into_iter()
+    raw_comps.iter()
         .filter(|c| c.no_competition == Some(competition_id))
         .filter_map(|c| {
             let id = c.no_pat_compe?;
@@ -84,13 +190,14 @@ pub fn get_competitors_in_competition(file_path: &str, competition_id: i32) -> R
                 club_id, // stored as Option
                 affiliation: club.and_then(|cl| cl.abreviation.clone().or(cl.nom_du_club.clone())),
                 club_name: club.and_then(|cl| cl.nom_du_club.clone()),
+                region: club.and_then(|cl| cl.no_region).and_then(|id| region_map.get(&id)).map(|r| r.trim().to_string()),
                 rank: c.rang,
                 removed: c.retirer.map(|v| v != 0),
-                group: c.groupe,
+                group: c.groupe.clone(),
                 helmet_id: c.no_casque,
             })
         })
-        .collect())
+        .collect()
 }
 
 // Program and Race Logic
@@ -102,17 +209,21 @@ pub struct Distance {
     pub track: i32,
 }
 
-pub fn get_distances(file_path: &str) -> Result<Vec<Distance>> {
-    let raw: Vec<TDistancesStandards> = read_table(file_path, "TDistances_Standards")?;
-    Ok(raw.into_iter().filter_map(|row| {
+pub fn get_distances<S: DataSource>(source: &S) -> Result<Vec<Distance>> {
+    let raw: Vec<TDistancesStandards> = source.read_table("TDistances_Standards")?;
+    Ok(build_distances(&raw))
+}
+
+pub fn build_distances(raw: &[TDistancesStandards]) -> Vec<Distance> {
+    raw.iter().filter_map(|row| {
         let track = if row.distance.as_ref().map(|d| d.contains("(111)")).unwrap_or(false) { 111 } else { 100 };
         Some(Distance {
             id: row.no_distance?,
-            name: row.distance,
+            name: row.distance.clone(),
             length: row.longueur_epreuve,
             track,
         })
-    }).collect())
+    }).collect()
 }
 
 #[derive(Debug, Clone)]
@@ -124,15 +235,20 @@ pub struct ProgramItem {
     pub group: Option<String>,
     pub length: Option<i32>,
     pub track: i32,
+    /// TProg_Courses.OrdreSequence, block.position (1.002 is the second item of block 1)
+    pub order: Option<f64>,
 }
 
-pub fn get_programs(file_path: &str, competition_id: i32) -> Result<Vec<ProgramItem>> {
-    let distances = get_distances(file_path)?;
-    let dist_map: HashMap<i32, Distance> = distances.into_iter().map(|d| (d.id, d)).collect();
-    
-    let raw: Vec<TProgCourses> = read_table(file_path, "TProg_Courses")?;
-    
-    Ok(raw.into_iter()
+pub fn get_programs<S: DataSource>(source: &S, competition_id: i32) -> Result<Vec<ProgramItem>> {
+    let distances = get_distances(source)?;
+    let raw: Vec<TProgCourses> = source.read_table("TProg_Courses")?;
+    Ok(build_programs(&distances, &raw, competition_id))
+}
+
+pub fn build_programs(distances: &[Distance], raw: &[TProgCourses], competition_id: i32) -> Vec<ProgramItem> {
+    let dist_map: HashMap<i32, &Distance> = distances.iter().map(|d| (d.id, d)).collect();
+
+    raw.iter()
         .filter(|row| row.no_competition == Some(competition_id))
         .filter_map(|row| {
             let dist_id = row.no_distance?;
@@ -141,13 +257,15 @@ pub fn get_programs(file_path: &str, competition_id: i32) -> Result<Vec<ProgramI
                 id: row.cle_distances_compe?,
                 competition_id: row.no_competition?,
                 distance_id: dist_id,
-                distance: row.distance,
-                group: row.groupe,
+                distance: row.distance.clone(),
+                group: row.groupe.clone(),
                 length: distance.and_then(|d| d.length),
                 track: distance.map(|d| d.track).unwrap_or(100),
+                // 0 means not scheduled
+                order: row.ordre_sequence.filter(|o| *o > 0.0),
             })
         })
-        .collect())
+        .collect()
 }
 
 #[derive(Debug, Clone)]
@@ -158,16 +276,21 @@ pub struct Race {
     pub track: i32,
     pub program_item_id: i32,
     pub sequence: Option<i32>,
-    pub round: Option<String>,
+    pub round: Round,
+    /// Order of the race's program item, see `ProgramItem::order`
+    pub program_order: Option<f64>,
 }
 
-pub fn get_races(file_path: &str, competition_id: i32) -> Result<Vec<Race>> {
-    let programs = get_programs(file_path, competition_id)?;
-    let prog_map: HashMap<i32, ProgramItem> = programs.iter().map(|p| (p.id, p.clone())).collect();
-    
-    let raw: Vec<TVagues> = read_table(file_path, "TVagues")?;
-    
-    Ok(raw.into_iter()
+pub fn get_races<S: DataSource>(source: &S, competition_id: i32) -> Result<Vec<Race>> {
+    let programs = get_programs(source, competition_id)?;
+    let raw: Vec<TVagues> = source.read_table("TVagues")?;
+    Ok(build_races(&programs, &raw, competition_id))
+}
+
+pub fn build_races(programs: &[ProgramItem], raw: &[TVagues], competition_id: i32) -> Vec<Race> {
+    let prog_map: HashMap<i32, &ProgramItem> = programs.iter().map(|p| (p.id, p)).collect();
+
+    raw.iter()
         .filter_map(|row| {
             let cle_dist = row.cle_distances_compe?;
             let prog = prog_map.get(&cle_dist)?;
@@ -176,15 +299,16 @@ pub fn get_races(file_path: &str, competition_id: i32) -> Result<Vec<Race>> {
             }
             Some(Race {
                 id: row.cle_tvagues?,
-                name: row.no_vague.unwrap_or_default(),
+                name: row.no_vague.clone().unwrap_or_default(),
                 distance: prog.length,
                 track: prog.track,
                 program_item_id: prog.id,
                 sequence: row.seq,
-                round: row.qual_ou_fin,
+                round: Round::parse(row.qual_ou_fin.as_deref(), row.no_vague.as_deref().unwrap_or_default()),
+                program_order: prog.order,
             })
         })
-        .collect())
+        .collect()
 }
 
 #[derive(Debug, Clone)]
@@ -196,18 +320,22 @@ pub struct Lane {
     pub time: Option<String>,
     pub position: Option<i32>,
     pub start_position: Option<i32>,
+    /// Matched from a FinishLynx .LIF file, see `RaceData::merge_results`
+    pub lynx_result: Option<LaneResult>,
 }
 
-pub fn get_lanes(file_path: &str, competition_id: i32) -> Result<Vec<Lane>> {
-    let races = get_races(file_path, competition_id)?;
-    let race_ids: Vec<i32> = races.iter().map(|r| r.id).collect();
-    
-    let competitors = get_competitors_in_competition(file_path, competition_id)?;
-    let comp_map: HashMap<i32, CompetitorInCompetition> = competitors.into_iter().map(|c| (c.id, c)).collect();
-    
-    let raw: Vec<TPatVagues> = read_table(file_path, "TPatVagues")?;
-    
-    Ok(raw.into_iter()
+pub fn get_lanes<S: DataSource>(source: &S, competition_id: i32) -> Result<Vec<Lane>> {
+    let races = get_races(source, competition_id)?;
+    let competitors = get_competitors_in_competition(source, competition_id)?;
+    let raw: Vec<TPatVagues> = source.read_table("TPatVagues")?;
+    Ok(build_lanes(&races, &competitors, &raw))
+}
+
+pub fn build_lanes(races: &[Race], competitors: &[CompetitorInCompetition], raw: &[TPatVagues]) -> Vec<Lane> {
+    let race_ids: HashSet<i32> = races.iter().map(|r| r.id).collect();
+    let comp_map: HashMap<i32, &CompetitorInCompetition> = competitors.iter().map(|c| (c.id, c)).collect();
+
+    raw.iter()
         .filter_map(|lane| {
             let race_id = lane.cle_tvagues?;
             if !race_ids.contains(&race_id) {
@@ -221,12 +349,13 @@ pub fn get_lanes(file_path: &str, competition_id: i32) -> Result<Vec<Lane>> {
                 race_id,
                 skater_in_competition_id: no_pat_compe,
                 skater_upid: comp.and_then(|c| c.competitor_id.clone()),
-                time: lane.temps,
+                time: lane.temps.clone(),
                 position: lane.rang,
                 start_position: lane.no_casque,
+                lynx_result: None,
             })
         })
-        .collect())
+        .collect()
 }
 
 // Helpers
@@ -239,6 +368,32 @@ pub fn letter_to_number(letter: &str) -> i32 {
     }
 }
 
+/// Running order: program block (the whole part of OrdreSequence), then
+/// TVagues.Seq, then the program item's position. Races missing these go
+/// after those that have them, and ties fall back to `race_compare`.
+pub fn running_order_compare(a: &Race, b: &Race) -> std::cmp::Ordering {
+    fn key(race: &Race) -> (Option<i64>, Option<i32>, Option<f64>) {
+        let block = race.program_order.map(|o| o.trunc() as i64);
+        (block, race.sequence.filter(|s| *s > 0), race.program_order)
+    }
+    let (a_block, a_seq, a_order) = key(a);
+    let (b_block, b_seq, b_order) = key(b);
+
+    missing_last(a_block, b_block, |x, y| x.cmp(&y))
+        .then_with(|| missing_last(a_seq, b_seq, |x, y| x.cmp(&y)))
+        .then_with(|| missing_last(a_order, b_order, |x, y| x.total_cmp(&y)))
+        .then_with(|| race_compare(a, b))
+}
+
+fn missing_last<T>(a: Option<T>, b: Option<T>, cmp: impl Fn(T, T) -> std::cmp::Ordering) -> std::cmp::Ordering {
+    match (a, b) {
+        (Some(a), Some(b)) => cmp(a, b),
+        (Some(_), None) => std::cmp::Ordering::Less,
+        (None, Some(_)) => std::cmp::Ordering::Greater,
+        (None, None) => std::cmp::Ordering::Equal,
+    }
+}
+
 pub fn race_compare(a: &Race, b: &Race) -> std::cmp::Ordering {
     // Assuming simple comparison for now, or recreating the JS logic
     // JS: aNum - bNum or letter compare
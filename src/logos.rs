@@ -0,0 +1,99 @@
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// Which club attribute names the logo file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum LogoKey {
+    /// Club abbreviation, e.g. "RICH"
+    #[default]
+    Affiliation,
+    /// GCPV club number (TClubs.NoClub)
+    Club,
+    /// Region abbreviation of the club, e.g. "BC"
+    Region,
+}
+
+impl LogoKey {
+    pub const ALL: [LogoKey; 3] = [LogoKey::Affiliation, LogoKey::Club, LogoKey::Region];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            LogoKey::Affiliation => "Affiliation",
+            LogoKey::Club => "Club ID",
+            LogoKey::Region => "Region",
+        }
+    }
+}
+
+impl std::str::FromStr for LogoKey {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "affiliation" | "abbreviation" => Ok(LogoKey::Affiliation),
+            "club" | "club_id" => Ok(LogoKey::Club),
+            "region" => Ok(LogoKey::Region),
+            other => Err(anyhow::anyhow!("Unknown logo key '{}' (expected affiliation, club or region)", other)),
+        }
+    }
+}
+
+/// Where `affiliationUrl` points.
+///
+/// `base` is either a folder, giving `<folder>/<key>.png`, or a template with
+/// `{key}`, `{affiliation}`, `{club_id}` and `{region}` placeholders, e.g.
+/// `https://example.com/logos/{region}.svg`.
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LogoOptions {
+    pub base: String,
+    pub key: LogoKey,
+    /// Used when there is no logo name, or a local logo file doesn't exist.
+    pub fallback: String,
+}
+
+impl LogoOptions {
+    /// Reads `LOGO_BASE`, `LOGO_KEY` and `LOGO_FALLBACK`.
+    pub fn from_env() -> Self {
+        Self {
+            base: std::env::var("LOGO_BASE").unwrap_or_default(),
+            key: std::env::var("LOGO_KEY").ok().and_then(|v| v.parse().ok()).unwrap_or_default(),
+            fallback: std::env::var("LOGO_FALLBACK").unwrap_or_default(),
+        }
+    }
+
+    /// The logo URL or path for a club. Empty when no base is configured.
+    pub fn resolve(&self, affiliation: Option<&str>, club_id: Option<i32>, region: Option<&str>) -> String {
+        if self.base.is_empty() {
+            return String::new();
+        }
+        let club_id = club_id.map(|id| id.to_string());
+        let key = match self.key {
+            LogoKey::Affiliation => affiliation,
+            LogoKey::Club => club_id.as_deref(),
+            LogoKey::Region => region,
+        };
+        let Some(key) = key.map(str::trim).filter(|k| !k.is_empty()) else {
+            return self.fallback.clone();
+        };
+
+        let logo = if self.base.contains('{') {
+            self.base
+                .replace("{key}", key)
+                .replace("{affiliation}", affiliation.unwrap_or("").trim())
+                .replace("{club_id}", club_id.as_deref().unwrap_or(""))
+                .replace("{region}", region.unwrap_or("").trim())
+        } else {
+            format!("{}/{}.png", self.base.trim_end_matches(['/', '\\']), key)
+        };
+
+        if !self.fallback.is_empty() && !is_url(&logo) && !Path::new(&logo).exists() {
+            return self.fallback.clone();
+        }
+        logo
+    }
+}
+
+fn is_url(s: &str) -> bool {
+    s.starts_with("http://") || s.starts_with("https://")
+}
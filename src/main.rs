@@ -1,7 +1,13 @@
 use eframe::egui;
 use rust_gcpv_lynx_export::gui::GcpvApp;
+use rust_gcpv_lynx_export::cli::{self, Launch};
 
 fn main() -> eframe::Result<()> {
+    let pat_file = match cli::launch_mode(std::env::args_os().skip(1)) {
+        Launch::Cli => std::process::exit(cli::main()),
+        Launch::Gui { pat_file } => pat_file,
+    };
+
     let native_options = eframe::NativeOptions {
         viewport: egui::ViewportBuilder::default()
             .with_inner_size([600.0, 400.0])
@@ -12,6 +18,6 @@ fn main() -> eframe::Result<()> {
     eframe::run_native(
         "GCPV Lynx Export",
         native_options,
-        Box::new(|cc| Ok(Box::new(GcpvApp::new(cc)))),
+        Box::new(|cc| Ok(Box::new(GcpvApp::new(cc, pat_file)))),
     )
 }
@@ -0,0 +1,357 @@
+use serde::{Deserialize, Serialize, Serializer};
+use std::cmp::Ordering;
+use std::fmt;
+
+/// Why a skater has no (valid) time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum TimeCode {
+    /// Penalty, possibly next to the time skated
+    Pen,
+    /// Did not finish
+    Dnf,
+    /// Disqualified
+    Dq,
+    /// Did not start
+    Dns,
+}
+
+impl TimeCode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TimeCode::Pen => "PEN",
+            TimeCode::Dnf => "DNF",
+            TimeCode::Dq => "DQ",
+            TimeCode::Dns => "DNS",
+        }
+    }
+
+    fn find(s: &str) -> Option<Self> {
+        let upper = s.to_uppercase();
+        let words: Vec<&str> = upper.split(|c: char| !c.is_ascii_alphabetic()).collect();
+        let has = |code: &str| words.contains(&code);
+        if has("DNS") {
+            Some(TimeCode::Dns)
+        } else if has("DQ") || has("DSQ") {
+            Some(TimeCode::Dq)
+        } else if has("DNF") {
+            Some(TimeCode::Dnf)
+        } else if has("PEN") || has("P") {
+            Some(TimeCode::Pen)
+        } else {
+            None
+        }
+    }
+}
+
+/// How many decimals a time is shown with. Extra digits are truncated, as
+/// skating times are, never rounded up.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum TimePrecision {
+    Seconds,
+    Tenths,
+    #[default]
+    Hundredths,
+    Thousandths,
+}
+
+impl TimePrecision {
+    pub const ALL: [TimePrecision; 4] = [
+        TimePrecision::Seconds,
+        TimePrecision::Tenths,
+        TimePrecision::Hundredths,
+        TimePrecision::Thousandths,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            TimePrecision::Seconds => "Seconds (44)",
+            TimePrecision::Tenths => "Tenths (44.1)",
+            TimePrecision::Hundredths => "Hundredths (44.11)",
+            TimePrecision::Thousandths => "Thousandths (44.113)",
+        }
+    }
+
+    fn decimals(&self) -> u32 {
+        match self {
+            TimePrecision::Seconds => 0,
+            TimePrecision::Tenths => 1,
+            TimePrecision::Hundredths => 2,
+            TimePrecision::Thousandths => 3,
+        }
+    }
+}
+
+impl std::str::FromStr for TimePrecision {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "seconds" | "0" => Ok(TimePrecision::Seconds),
+            "tenths" | "1" => Ok(TimePrecision::Tenths),
+            "hundredths" | "2" => Ok(TimePrecision::Hundredths),
+            "thousandths" | "3" => Ok(TimePrecision::Thousandths),
+            other => Err(anyhow::anyhow!("Unknown time precision '{}' (expected seconds, tenths, hundredths or thousandths)", other)),
+        }
+    }
+}
+
+/// A finish time, a status code, or both (a time with a penalty marker).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct RaceTime {
+    millis: Option<u64>,
+    code: Option<TimeCode>,
+}
+
+impl RaceTime {
+    pub fn from_millis(millis: u64) -> Self {
+        Self { millis: Some(millis), code: None }
+    }
+
+    pub fn from_code(code: TimeCode) -> Self {
+        Self { millis: None, code: Some(code) }
+    }
+
+    /// Parses a time as people and Lynx write it: `42.315`, `1:02.44`,
+    /// `1:02:03.5`, `DNF`, `DQ`, `DNS`, or a time with a penalty marker
+    /// such as `44.11 PEN`. Plain numbers are seconds. Returns `None` for
+    /// empty or unreadable text.
+    pub fn parse(s: &str) -> Option<Self> {
+        let s = s.trim();
+        let code = TimeCode::find(s);
+        let digits: String = s.chars().filter(|c| c.is_ascii_digit() || *c == '.' || *c == ':').collect();
+        let millis = parse_clock(&digits);
+        if millis.is_none() && code.is_none() {
+            return None;
+        }
+        Some(Self { millis, code })
+    }
+
+    /// Parses a GCPV `Temps` value. GCPV stores times as a single number
+    /// packed as minutes.secondshundredths (44.11s is 0.4411, 1:02.44 is
+    /// 1.0244), with 0 meaning no time. Text in any of the `parse` forms
+    /// (with a colon or a status code) is read as such.
+    pub fn from_gcpv(s: &str) -> Option<Self> {
+        let s = s.trim();
+        if s.contains(':') || s.chars().any(|c| c.is_ascii_alphabetic()) {
+            return Self::parse(s);
+        }
+        let value: f64 = s.parse().ok()?;
+        // MSSCC as one integer; anything that doesn't fit is corrupt
+        let scaled = (value * 10000.0).round();
+        if !scaled.is_finite() || scaled <= 0.0 || scaled >= u64::MAX as f64 {
+            return None;
+        }
+        let digits = scaled as u64;
+        let (minutes, seconds_hundredths) = (digits / 10000, digits % 10000);
+        let millis = minutes.checked_mul(60_000)?.checked_add(seconds_hundredths * 10)?;
+        Some(Self::from_millis(millis))
+    }
+
+    /// The packed GCPV value for this time, truncated to hundredths.
+    pub fn to_gcpv(&self) -> Option<f64> {
+        let hundredths = self.millis? / 10;
+        let (minutes, rest) = (hundredths / 6000, hundredths % 6000);
+        Some(minutes as f64 + rest as f64 / 10000.0)
+    }
+
+    /// The same time with a status code attached, e.g. a DQ from the place column.
+    pub fn with_code(self, code: TimeCode) -> Self {
+        Self { code: Some(code), ..self }
+    }
+
+    pub fn millis(&self) -> Option<u64> {
+        self.millis
+    }
+
+    pub fn code(&self) -> Option<TimeCode> {
+        self.code
+    }
+
+    /// A time that counts: skated, with no status code attached.
+    pub fn is_valid(&self) -> bool {
+        self.millis.is_some() && self.code.is_none()
+    }
+
+    /// The time alone, e.g. `44.11` or `1:02.44`; `None` for a bare code.
+    pub fn format_time(&self, precision: TimePrecision) -> Option<String> {
+        self.millis.map(|ms| format_millis(ms, precision))
+    }
+
+    /// The time followed by any code (`44.11 PEN`), or just the code.
+    pub fn format(&self, precision: TimePrecision) -> String {
+        match (self.format_time(precision), self.code) {
+            (Some(time), Some(code)) => format!("{} {}", time, code.as_str()),
+            (Some(time), None) => time,
+            (None, Some(code)) => code.as_str().to_string(),
+            (None, None) => String::new(),
+        }
+    }
+}
+
+impl fmt::Display for RaceTime {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.format(TimePrecision::default()))
+    }
+}
+
+impl Serialize for RaceTime {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+/// Valid times first, fastest to slowest, then penalties, DNF, DQ and DNS.
+impl Ord for RaceTime {
+    fn cmp(&self, other: &Self) -> Ordering {
+        let key = |t: &RaceTime| (t.code, t.millis.is_none(), t.millis);
+        key(self).cmp(&key(other))
+    }
+}
+
+impl PartialOrd for RaceTime {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// `[[h:]m:]s[.fff]` to milliseconds.
+fn parse_clock(s: &str) -> Option<u64> {
+    if s.is_empty() {
+        return None;
+    }
+    let (clock, fraction) = s.split_once('.').unwrap_or((s, ""));
+    if !fraction.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    let mut seconds: u64 = 0;
+    for part in clock.split(':') {
+        seconds = seconds.checked_mul(60)?.checked_add(part.parse::<u64>().ok()?)?;
+    }
+    let millis: u64 = format!("{:0<3}", fraction)[..3].parse().ok()?;
+    seconds.checked_mul(1000)?.checked_add(millis)
+}
+
+fn format_millis(ms: u64, precision: TimePrecision) -> String {
+    let decimals = precision.decimals();
+    let (minutes, seconds) = (ms / 60_000, ms / 1000 % 60);
+    let whole = if minutes > 0 {
+        format!("{}:{:02}", minutes, seconds)
+    } else {
+        seconds.to_string()
+    };
+    if decimals == 0 {
+        return whole;
+    }
+    let fraction = ms % 1000 / 10u64.pow(3 - decimals);
+    format!("{}.{:0width$}", whole, fraction, width = decimals as usize)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn millis(s: &str) -> Option<u64> {
+        RaceTime::parse(s).and_then(|t| t.millis())
+    }
+
+    #[test]
+    fn parses_clock_times() {
+        assert_eq!(millis("42.315"), Some(42_315));
+        assert_eq!(millis("44.1"), Some(44_100));
+        assert_eq!(millis("44"), Some(44_000));
+        assert_eq!(millis("1:02.44"), Some(62_440));
+        assert_eq!(millis("1:02:03.5"), Some(3_723_500));
+        // Digits past thousandths are dropped
+        assert_eq!(millis(" 42.3159 "), Some(42_315));
+        assert_eq!(RaceTime::parse(""), None);
+        assert_eq!(RaceTime::parse("abc"), None);
+    }
+
+    #[test]
+    fn parses_codes() {
+        assert_eq!(RaceTime::parse("DNF"), Some(RaceTime::from_code(TimeCode::Dnf)));
+        assert_eq!(RaceTime::parse("dq"), Some(RaceTime::from_code(TimeCode::Dq)));
+        assert_eq!(RaceTime::parse("DNS"), Some(RaceTime::from_code(TimeCode::Dns)));
+
+        let penalty = RaceTime::parse("44.11 PEN").unwrap();
+        assert_eq!((penalty.millis(), penalty.code()), (Some(44_110), Some(TimeCode::Pen)));
+        assert!(!penalty.is_valid());
+        assert!(RaceTime::from_millis(44_110).is_valid());
+    }
+
+    #[test]
+    fn reads_gcpv_times() {
+        let gcpv = |s: &str| RaceTime::from_gcpv(s).and_then(|t| t.millis());
+        assert_eq!(gcpv("0.4411"), Some(44_110));
+        assert_eq!(gcpv("2.3541"), Some(155_410));
+        assert_eq!(gcpv("1.0244"), Some(62_440));
+        assert_eq!(RaceTime::from_gcpv("0"), None);
+        assert_eq!(RaceTime::from_gcpv(""), None);
+        // Text forms are read as `parse` reads them
+        assert_eq!(gcpv("1:02.44"), Some(62_440));
+        assert_eq!(RaceTime::from_gcpv("DQ"), Some(RaceTime::from_code(TimeCode::Dq)));
+    }
+
+    #[test]
+    fn rejects_absurd_times() {
+        assert_eq!(RaceTime::from_gcpv("99999999999999999999"), None);
+        assert_eq!(RaceTime::from_gcpv("1000000000000000"), None);
+        assert_eq!(RaceTime::from_gcpv("-0.4411"), None);
+        assert_eq!(RaceTime::from_gcpv("NaN"), None);
+        assert_eq!(RaceTime::from_gcpv("inf"), None);
+        assert_eq!(RaceTime::parse("99999999999999999999.5"), None);
+        assert_eq!(RaceTime::parse("18446744073709551:00"), None);
+        assert_eq!(RaceTime::parse("999999999999999999"), None);
+    }
+
+    #[test]
+    fn round_trips_gcpv_times() {
+        for value in ["0.4411", "2.3541", "1.0244", "0.0999"] {
+            let time = RaceTime::from_gcpv(value).unwrap();
+            let packed = time.to_gcpv().unwrap();
+            assert!((packed - value.parse::<f64>().unwrap()).abs() < 1e-9, "{} became {}", value, packed);
+            assert_eq!(RaceTime::from_gcpv(&packed.to_string()), Some(time));
+        }
+        assert_eq!(RaceTime::from_gcpv("0.4411").unwrap().to_string(), "44.11");
+        assert_eq!(RaceTime::from_gcpv("2.3541").unwrap().to_string(), "2:35.41");
+        assert_eq!(RaceTime::from_code(TimeCode::Dnf).to_gcpv(), None);
+    }
+
+    #[test]
+    fn truncates_instead_of_rounding() {
+        let time = RaceTime::from_millis(44_119);
+        assert_eq!(time.format(TimePrecision::Seconds), "44");
+        assert_eq!(time.format(TimePrecision::Tenths), "44.1");
+        assert_eq!(time.format(TimePrecision::Hundredths), "44.11");
+        assert_eq!(time.format(TimePrecision::Thousandths), "44.119");
+        assert_eq!(RaceTime::from_millis(119_999).format(TimePrecision::Hundredths), "1:59.99");
+        // GCPV only stores hundredths
+        assert_eq!(time.to_gcpv(), RaceTime::from_millis(44_110).to_gcpv());
+    }
+
+    #[test]
+    fn formats_codes() {
+        assert_eq!(RaceTime::parse("44.11 PEN").unwrap().to_string(), "44.11 PEN");
+        assert_eq!(RaceTime::from_code(TimeCode::Dnf).to_string(), "DNF");
+        assert_eq!(RaceTime::from_code(TimeCode::Dnf).format_time(TimePrecision::Hundredths), None);
+    }
+
+    #[test]
+    fn sorts_valid_times_before_codes() {
+        let mut times: Vec<RaceTime> = ["DNS", "DNF", "45.0", "44.11 PEN", "44.0", "DQ", "1:02.44"]
+            .iter()
+            .map(|s| RaceTime::parse(s).unwrap())
+            .collect();
+        times.sort();
+        let sorted: Vec<String> = times.iter().map(|t| t.to_string()).collect();
+        assert_eq!(sorted, ["44.00", "45.00", "1:02.44", "44.11 PEN", "DNF", "DQ", "DNS"]);
+    }
+
+    #[test]
+    fn parses_precision_names() {
+        assert_eq!("tenths".parse::<TimePrecision>().unwrap(), TimePrecision::Tenths);
+        assert_eq!("Thousandths".parse::<TimePrecision>().unwrap(), TimePrecision::Thousandths);
+        assert_eq!("2".parse::<TimePrecision>().unwrap(), TimePrecision::Hundredths);
+        assert!("minutes".parse::<TimePrecision>().is_err());
+    }
+}
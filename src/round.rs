@@ -0,0 +1,206 @@
+use serde::Serialize;
+
+/// Which round of a distance a race belongs to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Default)]
+#[serde(rename_all = "camelCase")]
+pub enum Round {
+    #[default]
+    Heat,
+    Repechage,
+    EighthFinal,
+    QuarterFinal,
+    SemiFinal,
+    FinalA,
+    FinalB,
+    /// Any other final (C, D, ...) or an unlettered one
+    Final,
+    TimeTrial,
+}
+
+impl Round {
+    /// Reads GCPV's `Qual_ou_Fin` ("Qual", "Demi", "Fin", ...), falling back to
+    /// hints in the race name. Finals A and B are told apart by the race letter.
+    pub fn parse(qual_ou_fin: Option<&str>, race_name: &str) -> Self {
+        let letter: String = race_name.chars().filter(|c| c.is_alphabetic()).collect();
+        qual_ou_fin
+            .and_then(|q| Self::from_code(q, &letter).or_else(|| Self::from_text(q, &letter)))
+            .or_else(|| Self::from_text(race_name, &letter))
+            .unwrap_or_default()
+    }
+
+    /// The codes GCPV writes to `Qual_ou_Fin`.
+    fn from_code(code: &str, letter: &str) -> Option<Self> {
+        match code.trim().to_lowercase().as_str() {
+            "qual" => Some(Round::Heat),
+            "rep" | "repech" => Some(Round::Repechage),
+            "huit" | "1/8" => Some(Round::EighthFinal),
+            "quart" | "1/4" => Some(Round::QuarterFinal),
+            "demi" | "1/2" => Some(Round::SemiFinal),
+            "fin" => Some(Self::final_for(letter)),
+            "clm" | "chrono" => Some(Round::TimeTrial),
+            _ => None,
+        }
+    }
+
+    fn final_for(letter: &str) -> Self {
+        match letter.to_uppercase().as_str() {
+            "A" => Round::FinalA,
+            "B" => Round::FinalB,
+            _ => Round::Final,
+        }
+    }
+
+    /// Loose matching for anything else, e.g. hand-entered text or race names.
+    fn from_text(text: &str, letter: &str) -> Option<Self> {
+        let text = text.trim().to_lowercase();
+        let has = |words: &[&str]| words.iter().any(|w| text.contains(w));
+        // Checked in this order since "demi-finale" contains "fin"
+        if has(&["rep"]) {
+            Some(Round::Repechage)
+        } else if has(&["huit", "eighth", "1/8"]) {
+            Some(Round::EighthFinal)
+        } else if has(&["quart", "quarter", "qf"]) {
+            Some(Round::QuarterFinal)
+        } else if has(&["demi", "semi", "sf"]) {
+            Some(Round::SemiFinal)
+        } else if has(&["fin"]) {
+            Some(Self::final_for(letter))
+        } else if has(&["chrono", "contre", "time trial", "clm", "tt"]) {
+            Some(Round::TimeTrial)
+        } else if has(&["qual", "heat", "s\u{e9}rie", "serie", "prel"]) {
+            Some(Round::Heat)
+        } else {
+            None
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Round::Heat => "Heat",
+            Round::Repechage => "Repechage",
+            Round::EighthFinal => "Eighth-final",
+            Round::QuarterFinal => "Quarter-final",
+            Round::SemiFinal => "Semi-final",
+            Round::FinalA => "Final A",
+            Round::FinalB => "Final B",
+            Round::Final => "Final",
+            Round::TimeTrial => "Time trial",
+        }
+    }
+
+    /// The round number written to the Lynx files. Finals share one number:
+    /// each race is its own Lynx event (see `writer::lynx_event_id`), so
+    /// final A and final B never collide.
+    pub fn lynx_number(&self) -> i32 {
+        match self {
+            Round::Heat | Round::TimeTrial => 1,
+            Round::Repechage => 2,
+            Round::EighthFinal => 3,
+            Round::QuarterFinal => 4,
+            Round::SemiFinal => 5,
+            Round::FinalA | Round::FinalB | Round::Final => 6,
+        }
+    }
+}
+
+/// Reads the JSON names (`semiFinal`) and labels (`Semi-final`), ignoring
+/// case, spaces, dashes and underscores.
+impl std::str::FromStr for Round {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        let key: String = s.chars().filter(|c| !matches!(c, ' ' | '-' | '_')).collect::<String>().to_lowercase();
+        match key.as_str() {
+            "heat" => Ok(Round::Heat),
+            "repechage" => Ok(Round::Repechage),
+            "eighthfinal" => Ok(Round::EighthFinal),
+            "quarterfinal" => Ok(Round::QuarterFinal),
+            "semifinal" => Ok(Round::SemiFinal),
+            "finala" => Ok(Round::FinalA),
+            "finalb" => Ok(Round::FinalB),
+            "final" => Ok(Round::Final),
+            "timetrial" => Ok(Round::TimeTrial),
+            _ => Err(anyhow::anyhow!("Unknown round '{}'", s.trim())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_gcpv_codes() {
+        let cases = [
+            ("Qual", "1A", Round::Heat),
+            ("Qual", "1G", Round::Heat),
+            ("Rep", "4A", Round::Repechage),
+            ("Huit", "5C", Round::EighthFinal),
+            ("1/8", "5C", Round::EighthFinal),
+            ("Quart", "6B", Round::QuarterFinal),
+            ("Demi", "7A", Round::SemiFinal),
+            ("Demi", "7F", Round::SemiFinal),
+            ("Fin", "9A", Round::FinalA),
+            ("Fin", "9b", Round::FinalB),
+            ("Fin", "9C", Round::Final),
+            ("Fin", "9D", Round::Final),
+            ("Fin", "9", Round::Final),
+            ("CLM", "2A", Round::TimeTrial),
+            (" fin ", "9A", Round::FinalA),
+        ];
+        for (code, race, round) in cases {
+            assert_eq!(Round::parse(Some(code), race), round, "{} {}", code, race);
+        }
+    }
+
+    #[test]
+    fn falls_back_to_loose_text() {
+        let cases = [
+            (Some("Demi-finale"), "3A", Round::SemiFinal),
+            (Some("Quart de finale"), "3A", Round::QuarterFinal),
+            (Some("1/8 de finale"), "3A", Round::EighthFinal),
+            (Some("Finale"), "3B", Round::FinalB),
+            (Some("Repêchage"), "3A", Round::Repechage),
+            (Some("Contre la montre"), "3A", Round::TimeTrial),
+            (Some("Série"), "3A", Round::Heat),
+            (Some("?"), "3A", Round::Heat),
+            (None, "Semi 3A", Round::SemiFinal),
+            (None, "3A", Round::Heat),
+        ];
+        for (code, race, round) in cases {
+            assert_eq!(Round::parse(code, race), round, "{:?} {}", code, race);
+        }
+    }
+
+    #[test]
+    fn exact_codes_win_over_race_names() {
+        // The race name hints at a final, but GCPV says heat
+        assert_eq!(Round::parse(Some("Qual"), "Final 3A"), Round::Heat);
+    }
+
+    #[test]
+    fn numbers_rounds_in_order() {
+        let rounds = [
+            Round::Heat,
+            Round::Repechage,
+            Round::EighthFinal,
+            Round::QuarterFinal,
+            Round::SemiFinal,
+            Round::FinalA,
+        ];
+        let numbers: Vec<i32> = rounds.iter().map(|r| r.lynx_number()).collect();
+        assert_eq!(numbers, [1, 2, 3, 4, 5, 6]);
+        assert_eq!(Round::FinalB.lynx_number(), Round::FinalA.lynx_number());
+        assert_eq!(Round::TimeTrial.lynx_number(), 1);
+    }
+
+    #[test]
+    fn reads_json_names_and_labels() {
+        for round in [Round::Heat, Round::Repechage, Round::EighthFinal, Round::QuarterFinal, Round::SemiFinal, Round::FinalA, Round::FinalB, Round::Final, Round::TimeTrial] {
+            assert_eq!(round.label().parse::<Round>().unwrap(), round);
+            let json = serde_json::to_value(round).unwrap();
+            assert_eq!(json.as_str().unwrap().parse::<Round>().unwrap(), round);
+        }
+        assert!("fourth".parse::<Round>().is_err());
+    }
+}
@@ -0,0 +1,171 @@
+use crate::app_logic::ReadOptions;
+use crate::db::Backend;
+use crate::logos::LogoOptions;
+use crate::watcher::WatchMode;
+use crate::writer::ExportOptions;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Storage key for the settings in use when the app was closed.
+pub const SETTINGS_KEY: &str = "settings";
+/// Storage key for the named profiles.
+pub const PROFILES_KEY: &str = "profiles";
+
+/// Everything an operator sets up in either window. Each app uses the fields
+/// it has; the rest are kept so profiles round-trip.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ExporterSettings {
+    pub pat_file: String,
+    pub competition_id: Option<i32>,
+    pub output_folder: String,
+    pub interval_seconds: u64,
+    pub watch_mode: WatchMode,
+    pub read_options: ReadOptions,
+    pub export_options: ExportOptions,
+    /// web_export only
+    pub port: u16,
+    /// web_export only
+    pub serve_logos: bool,
+}
+
+/// The environment (`DB_BACKEND`, `LOGO_*`, `SERVE_LOGOS`) only sets defaults
+/// for a first launch; saved settings win after that.
+impl Default for ExporterSettings {
+    fn default() -> Self {
+        Self {
+            pat_file: String::new(),
+            competition_id: None,
+            output_folder: String::new(),
+            interval_seconds: 60,
+            watch_mode: WatchMode::default(),
+            read_options: ReadOptions { backend: Backend::from_env(), ..Default::default() },
+            export_options: ExportOptions { logos: LogoOptions::from_env(), ..Default::default() },
+            port: 3030,
+            serve_logos: std::env::var("SERVE_LOGOS").is_ok_and(|v| v == "1" || v.eq_ignore_ascii_case("true")),
+        }
+    }
+}
+
+/// Named settings, e.g. "BC Cup Richmond" or "Provincials".
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Profiles {
+    profiles: BTreeMap<String, ExporterSettings>,
+    /// The profile last loaded or saved
+    pub selected: Option<String>,
+}
+
+impl Profiles {
+    /// Profile names, sorted.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.profiles.keys().map(String::as_str)
+    }
+
+    pub fn get(&self, name: &str) -> Option<&ExporterSettings> {
+        self.profiles.get(name)
+    }
+
+    /// Saves (or overwrites) a profile and selects it.
+    pub fn save(&mut self, name: &str, settings: ExporterSettings) -> Result<()> {
+        let name = name.trim();
+        if name.is_empty() {
+            anyhow::bail!("Profile name is empty");
+        }
+        self.profiles.insert(name.to_string(), settings);
+        self.selected = Some(name.to_string());
+        Ok(())
+    }
+
+    /// Selects a profile and returns its settings.
+    pub fn select(&mut self, name: &str) -> Option<ExporterSettings> {
+        let settings = self.profiles.get(name)?.clone();
+        self.selected = Some(name.to_string());
+        Some(settings)
+    }
+
+    pub fn delete(&mut self, name: &str) {
+        self.profiles.remove(name);
+        if self.selected.as_deref() == Some(name) {
+            self.selected = None;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings(pat_file: &str) -> ExporterSettings {
+        ExporterSettings { pat_file: pat_file.to_string(), ..Default::default() }
+    }
+
+    #[test]
+    fn saves_named_profiles() {
+        let mut profiles = Profiles::default();
+        assert!(profiles.save("", settings("a.pat")).is_err());
+        assert!(profiles.save("  \t", settings("a.pat")).is_err());
+        assert_eq!(profiles.names().count(), 0);
+        assert_eq!(profiles.selected, None);
+
+        profiles.save(" Provincials ", settings("prov.pat")).unwrap();
+        profiles.save("BC Cup", settings("bc.pat")).unwrap();
+        assert_eq!(profiles.names().collect::<Vec<_>>(), ["BC Cup", "Provincials"]);
+        assert_eq!(profiles.selected.as_deref(), Some("BC Cup"));
+        assert_eq!(profiles.get("Provincials").unwrap().pat_file, "prov.pat");
+
+        // Saving again overwrites
+        profiles.save("BC Cup", settings("bc2.pat")).unwrap();
+        assert_eq!(profiles.names().count(), 2);
+        assert_eq!(profiles.get("BC Cup").unwrap().pat_file, "bc2.pat");
+    }
+
+    #[test]
+    fn selects_and_deletes_profiles() {
+        let mut profiles = Profiles::default();
+        profiles.save("Provincials", settings("prov.pat")).unwrap();
+        profiles.save("BC Cup", settings("bc.pat")).unwrap();
+
+        assert_eq!(profiles.select("Missing"), None);
+        assert_eq!(profiles.selected.as_deref(), Some("BC Cup"));
+        assert_eq!(profiles.select("Provincials").unwrap().pat_file, "prov.pat");
+        assert_eq!(profiles.selected.as_deref(), Some("Provincials"));
+
+        // Deleting another profile keeps the selection
+        profiles.delete("BC Cup");
+        assert_eq!(profiles.selected.as_deref(), Some("Provincials"));
+        profiles.delete("Provincials");
+        assert_eq!(profiles.selected, None);
+        assert_eq!(profiles.names().count(), 0);
+    }
+
+    #[test]
+    fn reads_settings_with_missing_fields() {
+        let mut saved = settings("comp.pat");
+        saved.export_options.precision = crate::race_time::TimePrecision::Tenths;
+        saved.read_options.snapshot_copy = true;
+        let json = serde_json::to_string(&saved).unwrap();
+        assert_eq!(serde_json::from_str::<ExporterSettings>(&json).unwrap(), saved);
+
+        // Settings saved by an older version: fields missing, or since removed
+        let old: ExporterSettings = serde_json::from_str(r#"{
+            "pat_file": "comp.pat",
+            "results_folder": "C:\\Lynx",
+            "read_options": {"snapshot_copy": true},
+            "export_options": {}
+        }"#).unwrap();
+        // Nested options missing fields take their type's defaults
+        let expected = ExporterSettings {
+            read_options: ReadOptions { snapshot_copy: true, ..Default::default() },
+            export_options: ExportOptions::default(),
+            ..settings("comp.pat")
+        };
+        assert_eq!(old, expected);
+
+        let profiles: Profiles = serde_json::from_str(r#"{"profiles": {"Old": {"port": 8080}}}"#).unwrap();
+        assert_eq!(profiles.get("Old").unwrap().port, 8080);
+        assert_eq!(profiles.get("Old").unwrap().interval_seconds, 60);
+        assert_eq!(profiles.selected, None);
+    }
+}
@@ -0,0 +1,167 @@
+use crate::db::{self, Backend};
+use crate::jet::JetFile;
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+
+/// Somewhere GCPV tables can be read from.
+pub trait DataSource {
+    fn list_tables(&self) -> Result<Vec<String>>;
+    fn read_table<T: for<'de> Deserialize<'de>>(&self, table_name: &str) -> Result<Vec<T>>;
+}
+
+impl<S: DataSource + ?Sized> DataSource for &S {
+    fn list_tables(&self) -> Result<Vec<String>> {
+        (**self).list_tables()
+    }
+
+    fn read_table<T: for<'de> Deserialize<'de>>(&self, table_name: &str) -> Result<Vec<T>> {
+        (**self).read_table(table_name)
+    }
+}
+
+/// Reads a .pat file with the built-in Jet reader. The file is loaded once on open.
+pub struct JetSource {
+    jet: JetFile,
+}
+
+impl JetSource {
+    pub fn open(file_path: &str) -> Result<Self> {
+        Ok(Self { jet: JetFile::open(file_path)? })
+    }
+}
+
+impl DataSource for JetSource {
+    fn list_tables(&self) -> Result<Vec<String>> {
+        self.jet.table_names()
+    }
+
+    fn read_table<T: for<'de> Deserialize<'de>>(&self, table_name: &str) -> Result<Vec<T>> {
+        db::read_jet_table(&self.jet, table_name)
+    }
+}
+
+/// Reads a .pat file through `mdb-export`.
+pub struct MdbExportSource {
+    file_path: String,
+}
+
+impl MdbExportSource {
+    pub fn new(file_path: &str) -> Self {
+        Self { file_path: file_path.to_string() }
+    }
+}
+
+impl DataSource for MdbExportSource {
+    fn list_tables(&self) -> Result<Vec<String>> {
+        db::list_tables_with(Backend::MdbExport, &self.file_path)
+    }
+
+    fn read_table<T: for<'de> Deserialize<'de>>(&self, table_name: &str) -> Result<Vec<T>> {
+        db::read_table_with(Backend::MdbExport, &self.file_path, table_name)
+    }
+}
+
+/// Reads a .pat file through the Access ODBC driver (Windows only).
+pub struct OdbcSource {
+    file_path: String,
+}
+
+impl OdbcSource {
+    pub fn new(file_path: &str) -> Self {
+        Self { file_path: file_path.to_string() }
+    }
+}
+
+impl DataSource for OdbcSource {
+    fn list_tables(&self) -> Result<Vec<String>> {
+        db::list_tables_with(Backend::Odbc, &self.file_path)
+    }
+
+    fn read_table<T: for<'de> Deserialize<'de>>(&self, table_name: &str) -> Result<Vec<T>> {
+        db::read_table_with(Backend::Odbc, &self.file_path, table_name)
+    }
+}
+
+/// A directory of saved tables, one `<Table>.json` (array of row objects) or
+/// `<Table>.csv` (mdb-export output) file per table.
+pub struct FixtureSource {
+    dir: PathBuf,
+}
+
+impl FixtureSource {
+    pub fn new<P: AsRef<Path>>(dir: P) -> Self {
+        Self { dir: dir.as_ref().to_path_buf() }
+    }
+}
+
+impl DataSource for FixtureSource {
+    fn list_tables(&self) -> Result<Vec<String>> {
+        let mut tables = Vec::new();
+        let entries = std::fs::read_dir(&self.dir)
+            .with_context(|| format!("Failed to read fixture directory {}", self.dir.display()))?;
+        for entry in entries {
+            let path = entry?.path();
+            let is_table = matches!(path.extension().and_then(|e| e.to_str()), Some("json") | Some("csv"));
+            if let (true, Some(stem)) = (is_table, path.file_stem().and_then(|s| s.to_str())) {
+                if !tables.iter().any(|t| t == stem) {
+                    tables.push(stem.to_string());
+                }
+            }
+        }
+        tables.sort();
+        Ok(tables)
+    }
+
+    fn read_table<T: for<'de> Deserialize<'de>>(&self, table_name: &str) -> Result<Vec<T>> {
+        let json_path = self.dir.join(format!("{}.json", table_name));
+        if json_path.exists() {
+            let file = std::fs::File::open(&json_path)?;
+            return serde_json::from_reader(std::io::BufReader::new(file))
+                .with_context(|| format!("Failed to deserialize {}", json_path.display()));
+        }
+
+        let csv_path = self.dir.join(format!("{}.csv", table_name));
+        let data = std::fs::read(&csv_path)
+            .with_context(|| format!("No fixture for table {} in {}", table_name, self.dir.display()))?;
+        db::deserialize_csv(&data, table_name)
+    }
+}
+
+/// A .pat file opened with a backend chosen at runtime.
+pub enum PatSource {
+    Jet(JetSource),
+    MdbExport(MdbExportSource),
+    Odbc(OdbcSource),
+}
+
+impl PatSource {
+    pub fn open(file_path: &str, backend: Backend) -> Result<Self> {
+        if !Path::new(file_path).exists() {
+            anyhow::bail!("File {} not found", file_path);
+        }
+        Ok(match backend {
+            Backend::Jet => PatSource::Jet(JetSource::open(file_path)?),
+            Backend::MdbExport => PatSource::MdbExport(MdbExportSource::new(file_path)),
+            Backend::Odbc => PatSource::Odbc(OdbcSource::new(file_path)),
+        })
+    }
+}
+
+impl DataSource for PatSource {
+    fn list_tables(&self) -> Result<Vec<String>> {
+        match self {
+            PatSource::Jet(s) => s.list_tables(),
+            PatSource::MdbExport(s) => s.list_tables(),
+            PatSource::Odbc(s) => s.list_tables(),
+        }
+    }
+
+    fn read_table<T: for<'de> Deserialize<'de>>(&self, table_name: &str) -> Result<Vec<T>> {
+        match self {
+            PatSource::Jet(s) => s.read_table(table_name),
+            PatSource::MdbExport(s) => s.read_table(table_name),
+            PatSource::Odbc(s) => s.read_table(table_name),
+        }
+    }
+}
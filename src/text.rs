@@ -0,0 +1,113 @@
+use encoding_rs::WINDOWS_1252;
+use serde::{Deserialize, Serialize};
+
+/// Character encoding of the Lynx files (EVT, SCH, PPL). `races.json` is always UTF-8.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum OutputEncoding {
+    #[default]
+    Utf8,
+    /// What FinishLynx expects on Western European Windows. Characters it
+    /// can't hold are folded to ASCII.
+    Windows1252,
+    /// Accents dropped (`Émilie` becomes `Emilie`), for any code page.
+    Ascii,
+}
+
+impl OutputEncoding {
+    pub const ALL: [OutputEncoding; 3] = [OutputEncoding::Utf8, OutputEncoding::Windows1252, OutputEncoding::Ascii];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            OutputEncoding::Utf8 => "UTF-8",
+            OutputEncoding::Windows1252 => "Windows-1252",
+            OutputEncoding::Ascii => "ASCII (accents removed)",
+        }
+    }
+
+    pub fn encode(&self, text: &str) -> Vec<u8> {
+        match self {
+            OutputEncoding::Utf8 => text.as_bytes().to_vec(),
+            OutputEncoding::Windows1252 => {
+                let mut bytes = Vec::with_capacity(text.len());
+                let mut buf = [0u8; 4];
+                for c in text.chars() {
+                    let (encoded, _, unmappable) = WINDOWS_1252.encode(c.encode_utf8(&mut buf));
+                    if unmappable {
+                        bytes.extend_from_slice(fold_char(c).as_bytes());
+                    } else {
+                        bytes.extend_from_slice(&encoded);
+                    }
+                }
+                bytes
+            }
+            OutputEncoding::Ascii => text.chars().flat_map(|c| fold_char(c).into_bytes()).collect(),
+        }
+    }
+}
+
+impl std::str::FromStr for OutputEncoding {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s.trim().to_lowercase().replace(['-', '_'], "").as_str() {
+            "utf8" => Ok(OutputEncoding::Utf8),
+            "windows1252" | "cp1252" | "ansi" => Ok(OutputEncoding::Windows1252),
+            "ascii" => Ok(OutputEncoding::Ascii),
+            other => Err(anyhow::anyhow!("Unknown encoding '{}' (expected utf-8, windows-1252 or ascii)", other)),
+        }
+    }
+}
+
+/// ASCII for one character; characters with no ASCII form become `?`.
+fn fold_char(c: char) -> String {
+    if c.is_ascii() {
+        return c.to_string();
+    }
+    match deunicode::deunicode_char(c) {
+        Some(folded) if !folded.is_empty() => folded.to_string(),
+        _ => "?".to_string(),
+    }
+}
+
+/// Clean-up applied to skater names and affiliations in every export.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NameOptions {
+    /// `Tremblay` becomes `TREMBLAY`
+    pub uppercase_last_names: bool,
+    /// Commas break the Lynx files, which are comma separated
+    pub strip_commas: bool,
+    /// Trim and collapse repeated whitespace, including line breaks
+    pub trim: bool,
+}
+
+impl Default for NameOptions {
+    fn default() -> Self {
+        Self {
+            uppercase_last_names: false,
+            strip_commas: true,
+            trim: true,
+        }
+    }
+}
+
+impl NameOptions {
+    /// A first name, affiliation or other text field.
+    pub fn clean(&self, text: &str) -> String {
+        let text = if self.strip_commas { text.replace(',', " ") } else { text.to_string() };
+        if self.trim {
+            text.split_whitespace().collect::<Vec<_>>().join(" ")
+        } else {
+            text
+        }
+    }
+
+    pub fn last_name(&self, last_name: &str) -> String {
+        let last_name = self.clean(last_name);
+        if self.uppercase_last_names {
+            last_name.to_uppercase()
+        } else {
+            last_name
+        }
+    }
+}
@@ -0,0 +1,312 @@
+use anyhow::{Context, Result};
+use notify::{Event, EventKind, PollWatcher, RecursiveMode, Watcher};
+use serde::{Deserialize, Serialize};
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Quiet period after the last write before a change is reported. GCPV writes in bursts.
+pub const DEFAULT_DEBOUNCE: Duration = Duration::from_millis(500);
+/// Upper bound on how long a continuous burst of writes can delay a notification.
+pub const MAX_DEBOUNCE_DELAY: Duration = Duration::from_secs(2);
+/// How often the polling watcher checks the file.
+pub const DEFAULT_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum WatchMode {
+    /// OS notifications, falling back to polling on network shares or if they are unavailable.
+    #[default]
+    Auto,
+    /// OS notifications only.
+    Native,
+    /// Check the file's metadata on an interval. Works on network shares.
+    Poll,
+}
+
+impl WatchMode {
+    pub const ALL: [WatchMode; 3] = [WatchMode::Auto, WatchMode::Native, WatchMode::Poll];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            WatchMode::Auto => "Auto",
+            WatchMode::Native => "Native",
+            WatchMode::Poll => "Poll",
+        }
+    }
+}
+
+impl std::str::FromStr for WatchMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "auto" => Ok(WatchMode::Auto),
+            "native" => Ok(WatchMode::Native),
+            "poll" | "polling" => Ok(WatchMode::Poll),
+            other => Err(anyhow::anyhow!("Unknown watch mode '{}' (expected auto, native or poll)", other)),
+        }
+    }
+}
+
+/// Watches a single file and reports debounced changes to it.
+pub struct FileWatcher {
+    changes: Receiver<()>,
+    mode: WatchMode,
+    // Dropping the watcher stops the notifications and the debounce thread.
+    _watcher: Box<dyn Watcher + Send>,
+}
+
+impl FileWatcher {
+    pub fn new(file_path: &str, mode: WatchMode) -> Result<Self> {
+        Self::with_timing(file_path, mode, DEFAULT_DEBOUNCE, DEFAULT_POLL_INTERVAL)
+    }
+
+    pub fn with_timing(file_path: &str, mode: WatchMode, debounce: Duration, poll_interval: Duration) -> Result<Self> {
+        let path = PathBuf::from(file_path);
+        let file_name = path.file_name()
+            .with_context(|| format!("{} is not a file path", file_path))?
+            .to_os_string();
+
+        let (event_tx, event_rx) = mpsc::channel::<()>();
+        let (change_tx, change_rx) = mpsc::channel::<()>();
+
+        let handler = || event_handler(file_name.clone(), event_tx.clone());
+        let (watcher, mode) = match mode {
+            WatchMode::Native => (native_watcher(&path, handler())?, WatchMode::Native),
+            WatchMode::Poll => (poll_watcher(&path, handler(), poll_interval)?, WatchMode::Poll),
+            WatchMode::Auto if is_network_path(&path) => (poll_watcher(&path, handler(), poll_interval)?, WatchMode::Poll),
+            WatchMode::Auto => match native_watcher(&path, handler()) {
+                Ok(watcher) => (watcher, WatchMode::Native),
+                Err(e) => {
+                    eprintln!("Native file watching unavailable, polling instead: {:?}", e);
+                    (poll_watcher(&path, handler(), poll_interval)?, WatchMode::Poll)
+                }
+            },
+        };
+        drop(event_tx);
+
+        thread::spawn(move || debounce_events(event_rx, change_tx, debounce));
+
+        Ok(Self { changes: change_rx, mode, _watcher: watcher })
+    }
+
+    /// The mode actually in use (`Auto` resolves to `Native` or `Poll`).
+    pub fn mode(&self) -> WatchMode {
+        self.mode
+    }
+
+    /// Non-blocking: true if the file changed since the last call.
+    pub fn poll_changed(&self) -> bool {
+        let mut changed = false;
+        while self.changes.try_recv().is_ok() {
+            changed = true;
+        }
+        changed
+    }
+
+    /// Blocks until the file changes or `timeout` elapses. Returns true on change.
+    pub fn wait_changed(&self, timeout: Duration) -> bool {
+        match self.changes.recv_timeout(timeout) {
+            Ok(()) => {
+                self.poll_changed();
+                true
+            }
+            Err(_) => false,
+        }
+    }
+}
+
+/// Forwards writes to the watched file, ignoring other files in the folder.
+fn event_handler(file_name: OsString, events: mpsc::Sender<()>) -> impl FnMut(notify::Result<Event>) + Send + 'static {
+    move |res: notify::Result<Event>| {
+        if let Ok(event) = res {
+            let relevant = !matches!(event.kind, EventKind::Access(_))
+                && event.paths.iter().any(|p| p.file_name() == Some(file_name.as_os_str()));
+            if relevant {
+                let _ = events.send(());
+            }
+        }
+    }
+}
+
+/// Collapses bursts of raw events into one notification, sent once the file has
+/// been quiet for `quiet`, or at most `MAX_DEBOUNCE_DELAY` after the first event.
+fn debounce_events(events: Receiver<()>, changes: mpsc::Sender<()>, quiet: Duration) {
+    while events.recv().is_ok() {
+        let first = Instant::now();
+        loop {
+            let elapsed = first.elapsed();
+            if elapsed >= MAX_DEBOUNCE_DELAY {
+                break;
+            }
+            match events.recv_timeout(quiet.min(MAX_DEBOUNCE_DELAY - elapsed)) {
+                Ok(()) => continue,
+                Err(RecvTimeoutError::Timeout) => break,
+                Err(RecvTimeoutError::Disconnected) => return,
+            }
+        }
+        if changes.send(()).is_err() {
+            return;
+        }
+    }
+}
+
+fn native_watcher<F>(path: &Path, handler: F) -> Result<Box<dyn Watcher + Send>>
+where
+    F: FnMut(notify::Result<Event>) + Send + 'static,
+{
+    let mut watcher = notify::recommended_watcher(handler).context("Failed to create file watcher")?;
+    // Watch the folder rather than the file, so replace-by-rename saves are still seen.
+    watcher.watch(watch_dir(path), RecursiveMode::NonRecursive)
+        .with_context(|| format!("Failed to watch {}", path.display()))?;
+    Ok(Box::new(watcher))
+}
+
+fn poll_watcher<F>(path: &Path, handler: F, interval: Duration) -> Result<Box<dyn Watcher + Send>>
+where
+    F: FnMut(notify::Result<Event>) + Send + 'static,
+{
+    // notify compares mtimes in whole seconds, so a write in the same second as
+    // the previous scan is only seen by the fallback interval. Hashing the
+    // contents instead would read the whole .pat on every poll.
+    let config = notify::Config::default().with_poll_interval(interval);
+    let mut watcher = PollWatcher::new(handler, config).context("Failed to create polling watcher")?;
+    watcher.watch(path, RecursiveMode::NonRecursive)
+        .with_context(|| format!("Failed to watch {}", path.display()))?;
+    Ok(Box::new(watcher))
+}
+
+fn watch_dir(path: &Path) -> &Path {
+    match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    }
+}
+
+/// UNC paths (\\server\share) don't deliver reliable change notifications.
+fn is_network_path(path: &Path) -> bool {
+    let s = path.to_string_lossy();
+    s.starts_with(r"\\") || s.starts_with("//")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use notify::event::{AccessKind, ModifyKind};
+
+    const QUIET: Duration = Duration::from_millis(100);
+
+    fn debouncer() -> (mpsc::Sender<()>, Receiver<()>, thread::JoinHandle<()>) {
+        let (event_tx, event_rx) = mpsc::channel();
+        let (change_tx, change_rx) = mpsc::channel();
+        let handle = thread::spawn(move || debounce_events(event_rx, change_tx, QUIET));
+        (event_tx, change_rx, handle)
+    }
+
+    fn count(changes: &Receiver<()>, within: Duration) -> usize {
+        let deadline = Instant::now() + within;
+        let mut count = 0;
+        while let Some(left) = deadline.checked_duration_since(Instant::now()) {
+            match changes.recv_timeout(left) {
+                Ok(()) => count += 1,
+                Err(_) => break,
+            }
+        }
+        count
+    }
+
+    #[test]
+    fn collapses_a_burst_into_one_change() {
+        let (events, changes, _) = debouncer();
+        for _ in 0..5 {
+            events.send(()).unwrap();
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(count(&changes, QUIET * 5), 1);
+
+        // A later write is its own change
+        events.send(()).unwrap();
+        assert_eq!(count(&changes, QUIET * 5), 1);
+    }
+
+    #[test]
+    fn waits_for_the_file_to_go_quiet() {
+        let (events, changes, _) = debouncer();
+        events.send(()).unwrap();
+        thread::sleep(QUIET / 2);
+        assert!(changes.try_recv().is_err());
+        assert_eq!(count(&changes, QUIET * 3), 1);
+    }
+
+    #[test]
+    fn reports_continuous_writes_after_the_max_delay() {
+        let (events, changes, _) = debouncer();
+        let start = Instant::now();
+        while start.elapsed() < MAX_DEBOUNCE_DELAY + QUIET * 3 {
+            events.send(()).unwrap();
+            if changes.try_recv().is_ok() {
+                break;
+            }
+            thread::sleep(QUIET / 4);
+        }
+        let waited = start.elapsed();
+        assert!(waited >= MAX_DEBOUNCE_DELAY && waited < MAX_DEBOUNCE_DELAY + QUIET * 3, "took {:?}", waited);
+    }
+
+    #[test]
+    fn stops_when_the_watcher_is_dropped() {
+        let (events, changes, handle) = debouncer();
+        events.send(()).unwrap();
+        drop(events);
+        handle.join().unwrap();
+        // The pending burst is dropped with the watcher
+        assert!(changes.recv().is_err());
+    }
+
+    #[test]
+    fn ignores_other_files_and_reads() {
+        let (tx, rx) = mpsc::channel();
+        let mut handler = event_handler(OsString::from("comp.pat"), tx);
+        let event = |kind, name: &str| Ok(Event::new(kind).add_path(PathBuf::from("/data").join(name)));
+
+        handler(event(EventKind::Modify(ModifyKind::Any), "other.pat"));
+        handler(event(EventKind::Access(AccessKind::Any), "comp.pat"));
+        handler(Err(notify::Error::generic("watch failed")));
+        assert!(rx.try_recv().is_err());
+
+        handler(event(EventKind::Modify(ModifyKind::Any), "comp.pat"));
+        handler(event(EventKind::Create(notify::event::CreateKind::File), "comp.pat"));
+        assert_eq!(rx.try_iter().count(), 2);
+    }
+
+    #[test]
+    fn polls_for_changes() {
+        let dir = std::env::temp_dir().join(format!("gcpv-watch-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("comp.pat");
+        std::fs::write(&file, "first").unwrap();
+
+        let watcher = FileWatcher::with_timing(file.to_str().unwrap(), WatchMode::Poll, QUIET, Duration::from_millis(50)).unwrap();
+        assert_eq!(watcher.mode(), WatchMode::Poll);
+        // The poll watcher compares whole-second mtimes, so write in a later second
+        thread::sleep(Duration::from_millis(1100));
+        assert!(!watcher.poll_changed());
+
+        std::fs::write(&file, "second, longer").unwrap();
+        assert!(watcher.wait_changed(Duration::from_secs(5)));
+        assert!(!watcher.poll_changed());
+        drop(watcher);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn detects_network_paths() {
+        assert!(is_network_path(Path::new(r"\\timing-pc\gcpv\comp.pat")));
+        assert!(is_network_path(Path::new("//timing-pc/gcpv/comp.pat")));
+        assert!(!is_network_path(Path::new(r"C:\gcpv\comp.pat")));
+        assert_eq!(watch_dir(Path::new("comp.pat")), Path::new("."));
+        assert_eq!("polling".parse::<WatchMode>().unwrap(), WatchMode::Poll);
+    }
+}
@@ -0,0 +1,853 @@
+use crate::app_logic::build_export_data;
+use crate::logic::Snapshot;
+use crate::logos::LogoOptions;
+use crate::round::Round;
+use crate::writer::{generate_race_json, ExportOptions, JsonRace};
+use anyhow::{Context, Result};
+use axum::{
+    extract::{Path, Query, State},
+    http::{header, HeaderMap, Method, StatusCode},
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        IntoResponse, Json, Response,
+    },
+    routing::{get, post},
+    Router,
+};
+use futures_util::stream::{self, Stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+use tokio::sync::broadcast::{self, error::RecvError};
+use tower_http::cors::CorsLayer;
+
+/// Races served by the web server, replaced whenever the export changes.
+pub type SharedRaces = Arc<RwLock<Vec<JsonRace>>>;
+/// Logo folder and fallback file served at /logos.
+pub type SharedLogos = Arc<RwLock<LogoOptions>>;
+
+#[derive(Clone)]
+pub struct WebState {
+    pub races: SharedRaces,
+    pub logos: SharedLogos,
+    /// Name of the race running now, moved from the GUI or `POST /control/current`.
+    current: Arc<RwLock<Option<String>>>,
+    /// Bumped on every change to `races` or `current`, while holding the `races` lock.
+    version: Arc<AtomicU64>,
+    updates: broadcast::Sender<RaceUpdate>,
+}
+
+impl Default for WebState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl WebState {
+    pub fn new() -> Self {
+        Self {
+            races: SharedRaces::default(),
+            logos: SharedLogos::default(),
+            current: Arc::new(RwLock::new(None)),
+            version: Arc::new(AtomicU64::new(0)),
+            updates: broadcast::channel(UPDATE_BUFFER).0,
+        }
+    }
+
+    /// Replaces the served races and pushes the changed ones to `/updates`
+    /// clients. Returns the new version, or `None` if nothing changed.
+    pub fn publish(&self, races: Vec<JsonRace>) -> Option<u64> {
+        let mut served = self.races.write().unwrap();
+        if *served == races {
+            return None;
+        }
+
+        let previous: HashMap<&str, &JsonRace> = served.iter().map(|r| (r.name.as_str(), r)).collect();
+        let changed: Vec<JsonRace> = races.iter()
+            .filter(|r| previous.get(r.name.as_str()) != Some(r))
+            .cloned()
+            .collect();
+        let removed: Vec<String> = served.iter()
+            .filter(|r| !races.iter().any(|n| n.name == r.name))
+            .map(|r| r.name.clone())
+            .collect();
+        let same_order = served.iter().map(|r| &r.name).eq(races.iter().map(|r| &r.name));
+
+        // A current race that no longer exists is cleared rather than left dangling
+        let mut current = self.current.write().unwrap();
+        if position(&races, current.as_deref()).is_none() {
+            *current = None;
+        }
+
+        let version = self.version.fetch_add(1, Ordering::SeqCst) + 1;
+        let update = RaceUpdate {
+            version,
+            races: changed,
+            removed,
+            order: (!same_order).then(|| races.iter().map(|r| r.name.clone()).collect()),
+            cursor: Cursor::of(&races, current.as_deref()),
+        };
+        *served = races;
+        // No receivers just means no one is listening
+        let _ = self.updates.send(update);
+        Some(version)
+    }
+
+    /// Builds the races of a snapshot and publishes them (see `publish`).
+    pub fn publish_snapshot(&self, snapshot: &Snapshot, options: &ExportOptions, competition_id: Option<i32>) -> Result<Option<u64>> {
+        let race_data = build_export_data(snapshot, options, competition_id)?;
+        let races = generate_race_json(
+            &race_data.races,
+            &race_data.programs,
+            &race_data.lanes,
+            &race_data.competitors,
+            &race_data.competitors_in_comp,
+            options
+        )?;
+        Ok(self.publish(races))
+    }
+
+    /// Serves the logo folder and fallback of `logos` at /logos, and returns
+    /// logo options pointing `affiliationUrl` there, named by the same key.
+    pub fn serve_logos(&self, logos: &LogoOptions) -> LogoOptions {
+        *self.logos.write().unwrap() = logos.clone();
+        LogoOptions {
+            base: format!("http://{}{}/{{key}}.png", HOST_PLACEHOLDER, LOGO_ROUTE),
+            key: logos.key,
+            fallback: format!("http://{}{}/default.png", HOST_PLACEHOLDER, LOGO_ROUTE),
+        }
+    }
+
+    pub fn version(&self) -> u64 {
+        self.version.load(Ordering::SeqCst)
+    }
+
+    pub fn current(&self) -> Option<String> {
+        self.current.read().unwrap().clone()
+    }
+
+    /// The current, next and on-deck races.
+    pub fn cursor(&self) -> Cursor {
+        let races = self.races.read().unwrap();
+        Cursor::of(&races, self.current().as_deref())
+    }
+
+    /// Makes `name` the current race (`None` clears it). Returns the new version.
+    pub fn set_current(&self, name: Option<&str>) -> Result<u64> {
+        // Held for writing, like `publish`, so versions go out in order
+        let races = self.races.write().unwrap();
+        let name = match name.map(str::trim) {
+            Some(name) => Some(races.iter()
+                .find(|r| r.name.eq_ignore_ascii_case(name))
+                .map(|r| r.name.clone())
+                .with_context(|| format!("No race {}", name))?),
+            None => None,
+        };
+        Ok(self.move_current(&races, name))
+    }
+
+    /// Moves the current race `step` races along the running order (back when
+    /// negative), stopping at the first and last race. With no current race,
+    /// any step starts at the first one. Returns the new version.
+    pub fn step_current(&self, step: i64) -> Result<u64> {
+        let races = self.races.write().unwrap();
+        if races.is_empty() {
+            anyhow::bail!("No races");
+        }
+        let index = match position(&races, self.current().as_deref()) {
+            Some(index) => (index as i64 + step).clamp(0, races.len() as i64 - 1) as usize,
+            None => 0,
+        };
+        let name = races[index].name.clone();
+        Ok(self.move_current(&races, Some(name)))
+    }
+
+    /// Callers hold the `races` write lock, which orders the version bump and send.
+    fn move_current(&self, races: &[JsonRace], name: Option<String>) -> u64 {
+        let cursor = Cursor::of(races, name.as_deref());
+        *self.current.write().unwrap() = name;
+        let version = self.version.fetch_add(1, Ordering::SeqCst) + 1;
+        let _ = self.updates.send(RaceUpdate {
+            version,
+            races: Vec::new(),
+            removed: Vec::new(),
+            order: None,
+            cursor,
+        });
+        version
+    }
+
+    fn snapshot(&self) -> RaceUpdate {
+        let races = self.races.read().unwrap();
+        RaceUpdate {
+            version: self.version(),
+            races: races.clone(),
+            removed: Vec::new(),
+            order: None,
+            cursor: Cursor::of(&races, self.current().as_deref()),
+        }
+    }
+}
+
+fn position(races: &[JsonRace], name: Option<&str>) -> Option<usize> {
+    name.and_then(|name| races.iter().position(|r| r.name == name))
+}
+
+/// Race names around the current race, in running order. Without a current
+/// race, the first race is next.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Cursor {
+    pub current: Option<String>,
+    pub next: Option<String>,
+    /// The race after next
+    pub on_deck: Option<String>,
+}
+
+impl Cursor {
+    fn of(races: &[JsonRace], current: Option<&str>) -> Self {
+        let next_index = position(races, current).map_or(0, |i| i + 1);
+        let name = |i: usize| races.get(i).map(|r| r.name.clone());
+        Self {
+            current: current.map(str::to_string),
+            next: name(next_index),
+            on_deck: name(next_index + 1),
+        }
+    }
+}
+
+/// Updates kept for slow `/updates` clients; those further behind get a new snapshot.
+const UPDATE_BUFFER: usize = 16;
+
+/// Sent on `/updates`: the full list of races (`snapshot`) or the races
+/// changed since the previous version (`update`).
+#[derive(Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RaceUpdate {
+    pub version: u64,
+    pub races: Vec<JsonRace>,
+    /// Names of races no longer in the competition
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub removed: Vec<String>,
+    /// All race names in running order, when races were added, removed or reordered
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub order: Option<Vec<String>>,
+    /// Sent with every update, so a move of the current race is an update with no races
+    #[serde(flatten)]
+    pub cursor: Cursor,
+}
+
+/// Where served logos are.
+pub const LOGO_ROUTE: &str = "/logos";
+
+/// Replaced in `affiliationUrl` by the host the client connected to.
+const HOST_PLACEHOLDER: &str = "{host}";
+
+/// 1x1 transparent PNG, served when there is no logo or fallback file.
+const PLACEHOLDER_PNG: &[u8] = &[
+    0x89, 0x50, 0x4E, 0x47, 0x0D, 0x0A, 0x1A, 0x0A, 0x00, 0x00, 0x00, 0x0D, 0x49, 0x48, 0x44, 0x52,
+    0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x01, 0x08, 0x06, 0x00, 0x00, 0x00, 0x1F, 0x15, 0xC4,
+    0x89, 0x00, 0x00, 0x00, 0x0B, 0x49, 0x44, 0x41, 0x54, 0x78, 0x9C, 0x63, 0x60, 0x00, 0x02, 0x00,
+    0x00, 0x05, 0x00, 0x01, 0x7A, 0x5E, 0xAB, 0x3F, 0x00, 0x00, 0x00, 0x00, 0x49, 0x45, 0x4E, 0x44,
+    0xAE, 0x42, 0x60, 0x82,
+];
+
+
+pub fn router(state: WebState) -> Router {
+    let cors = CorsLayer::new()
+        .allow_methods([Method::GET, Method::POST])
+        .allow_headers([header::CONTENT_TYPE])
+        .allow_origin(tower_http::cors::Any);
+
+    Router::new()
+        .route("/races", get(get_races))
+        .route("/races/:name", get(get_race))
+        .route("/events/:event", get(get_event))
+        .route("/skaters/:competitor_id", get(get_skater))
+        .route("/clubs/:affiliation", get(get_club))
+        .route("/logos/:file", get(get_logo))
+        .route("/updates", get(get_updates))
+        .route("/current", get(get_current))
+        .route("/next", get(get_next))
+        .route("/on-deck", get(get_on_deck))
+        .route("/control/current", post(set_current))
+        .layer(cors)
+        .with_state(state)
+}
+
+pub async fn run_server(port: u16, state: WebState) -> Result<()> {
+    let addr = std::net::SocketAddr::from(([0, 0, 0, 0], port));
+    let listener = tokio::net::TcpListener::bind(addr).await
+        .with_context(|| format!("Failed to listen on {}", addr))?;
+    println!("Listening on {}", addr);
+    axum::serve(listener, router(state)).await?;
+    Ok(())
+}
+
+/// Filters for `GET /races`; all optional, matched together.
+#[derive(Debug, Default, Deserialize)]
+pub struct RaceQuery {
+    pub group: Option<String>,
+    pub distance: Option<i32>,
+    pub round: Option<String>,
+}
+
+async fn get_races(State(state): State<WebState>, Query(query): Query<RaceQuery>, headers: HeaderMap) -> Response {
+    let round = match query.round.as_deref().filter(|r| !r.trim().is_empty()).map(str::parse::<Round>).transpose() {
+        Ok(round) => round,
+        Err(e) => return (StatusCode::BAD_REQUEST, e.to_string()).into_response(),
+    };
+    let group = query.group.as_deref().map(str::trim).filter(|g| !g.is_empty());
+    select_races(&state, &headers, |race| {
+        group.is_none_or(|g| race.group.as_deref().is_some_and(|rg| rg.trim().eq_ignore_ascii_case(g)))
+            && query.distance.is_none_or(|d| race.length == Some(d))
+            && round.is_none_or(|r| race.round == r)
+    })
+}
+
+async fn get_race(State(state): State<WebState>, Path(name): Path<String>, headers: HeaderMap) -> Response {
+    let races = races_matching(&state, &headers, |race| race.name.eq_ignore_ascii_case(name.trim()));
+    match races.into_iter().next() {
+        Some(race) => Json(race).into_response(),
+        None => (StatusCode::NOT_FOUND, format!("No race {}", name)).into_response(),
+    }
+}
+
+async fn get_event(State(state): State<WebState>, Path(event): Path<String>, headers: HeaderMap) -> Response {
+    select_races(&state, &headers, |race| race.event == event.trim())
+}
+
+/// Every heat the skater is in, with the other skaters in it.
+async fn get_skater(State(state): State<WebState>, Path(competitor_id): Path<String>, headers: HeaderMap) -> Response {
+    select_races(&state, &headers, |race| {
+        race.lanes.iter().any(|l| l.competitor_id.as_deref() == Some(competitor_id.trim()))
+    })
+}
+
+/// Every heat with a skater from the club.
+async fn get_club(State(state): State<WebState>, Path(affiliation): Path<String>, headers: HeaderMap) -> Response {
+    select_races(&state, &headers, |race| {
+        race.lanes.iter().any(|l| l.affiliation.as_deref().is_some_and(|a| a.eq_ignore_ascii_case(affiliation.trim())))
+    })
+}
+
+async fn get_current(State(state): State<WebState>, headers: HeaderMap) -> Response {
+    let name = state.cursor().current;
+    cursor_race(&state, &headers, name, "No current race")
+}
+
+async fn get_next(State(state): State<WebState>, headers: HeaderMap) -> Response {
+    let name = state.cursor().next;
+    cursor_race(&state, &headers, name, "No next race")
+}
+
+async fn get_on_deck(State(state): State<WebState>, headers: HeaderMap) -> Response {
+    let name = state.cursor().on_deck;
+    cursor_race(&state, &headers, name, "No race on deck")
+}
+
+fn cursor_race(state: &WebState, headers: &HeaderMap, name: Option<String>, missing: &str) -> Response {
+    let race = name.and_then(|name| races_matching(state, headers, |race| race.name == name).into_iter().next());
+    match race {
+        Some(race) => Json(race).into_response(),
+        None => (StatusCode::NOT_FOUND, missing.to_string()).into_response(),
+    }
+}
+
+/// Body of `POST /control/current`: `{"race": "12B"}` to jump, `{"race": null}`
+/// to clear, or `{"step": 1}` / `{"step": -1}` for next and previous.
+#[derive(Debug, Default, Deserialize)]
+pub struct CurrentRequest {
+    pub race: Option<String>,
+    pub step: Option<i64>,
+}
+
+async fn set_current(State(state): State<WebState>, Json(request): Json<CurrentRequest>) -> Response {
+    let result = match request.step {
+        Some(step) => state.step_current(step),
+        None => state.set_current(request.race.as_deref()),
+    };
+    match result {
+        Ok(_) => Json(state.cursor()).into_response(),
+        Err(e) => (StatusCode::NOT_FOUND, e.to_string()).into_response(),
+    }
+}
+
+fn select_races(state: &WebState, headers: &HeaderMap, filter: impl Fn(&JsonRace) -> bool) -> Response {
+    Json(races_matching(state, headers, filter)).into_response()
+}
+
+/// Copies the matching races, with served logo URLs pointed at the host the
+/// client used.
+fn races_matching(state: &WebState, headers: &HeaderMap, filter: impl Fn(&JsonRace) -> bool) -> Vec<JsonRace> {
+    let mut races: Vec<JsonRace> = state.races.read().unwrap().iter().filter(|r| filter(r)).cloned().collect();
+    set_host(&mut races, &host_of(headers));
+    races
+}
+
+fn host_of(headers: &HeaderMap) -> String {
+    headers.get(header::HOST).and_then(|h| h.to_str().ok()).unwrap_or("localhost").to_string()
+}
+
+fn set_host(races: &mut [JsonRace], host: &str) {
+    for lane in races.iter_mut().flat_map(|r| r.lanes.iter_mut()) {
+        if lane.affiliation_url.contains(HOST_PLACEHOLDER) {
+            lane.affiliation_url = lane.affiliation_url.replace(HOST_PLACEHOLDER, host);
+        }
+    }
+}
+
+/// Server-sent events: a `snapshot` on connect, then an `update` per change.
+/// Versions go up by one per change, so a client that sees a gap (or an
+/// update not newer than its snapshot, which it can ignore) knows where it
+/// stands; a client that falls too far behind is sent a new snapshot.
+async fn get_updates(State(state): State<WebState>, headers: HeaderMap) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+    let host = host_of(&headers);
+    // Subscribe first, so no change is missed between the snapshot and the updates
+    let receiver = state.updates.subscribe();
+    let snapshot = update_event("snapshot", state.snapshot(), &host);
+
+    let updates = stream::unfold((state, receiver, host), |(state, mut receiver, host)| async move {
+        let event = match receiver.recv().await {
+            Ok(update) => update_event("update", update, &host),
+            Err(RecvError::Lagged(_)) => update_event("snapshot", state.snapshot(), &host),
+            Err(RecvError::Closed) => return None,
+        };
+        Some((Ok(event), (state, receiver, host)))
+    });
+
+    Sse::new(stream::once(async move { Ok(snapshot) }).chain(updates)).keep_alive(KeepAlive::default())
+}
+
+fn update_event(name: &str, mut update: RaceUpdate, host: &str) -> Event {
+    set_host(&mut update.races, host);
+    Event::default()
+        .event(name)
+        .id(update.version.to_string())
+        .json_data(&update)
+        .unwrap_or_else(|e| Event::default().event("error").data(e.to_string()))
+}
+
+async fn get_logo(State(state): State<WebState>, Path(file): Path<String>) -> Response {
+    let logos = state.logos.read().unwrap().clone();
+    let mut candidates = Vec::new();
+    // Only plain file names, nothing outside the logo folder
+    if !logos.base.is_empty() && !file.contains(['/', '\\']) && !file.starts_with('.') {
+        candidates.push(std::path::Path::new(&logos.base).join(&file));
+    }
+    if !logos.fallback.is_empty() {
+        candidates.push(std::path::PathBuf::from(&logos.fallback));
+    }
+
+    for path in candidates {
+        if let Ok(bytes) = tokio::fs::read(&path).await {
+            return ([(header::CONTENT_TYPE, image_content_type(&path))], bytes).into_response();
+        }
+    }
+    ([(header::CONTENT_TYPE, "image/png")], PLACEHOLDER_PNG).into_response()
+}
+
+fn image_content_type(path: &std::path::Path) -> &'static str {
+    let ext = path.extension().and_then(|e| e.to_str()).unwrap_or("").to_lowercase();
+    match ext.as_str() {
+        "svg" => "image/svg+xml",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        _ => "image/png",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::writer::{JsonLane, RaceStatus};
+    use axum::body::Body;
+    use axum::http::Request;
+    use tower::ServiceExt;
+
+    fn lane(competitor_id: &str, affiliation: &str) -> JsonLane {
+        JsonLane {
+            start_position: Some(1),
+            helmet_id: None,
+            name: competitor_id.to_string(),
+            affiliation_url: format!("http://{}{}/{}.png", HOST_PLACEHOLDER, LOGO_ROUTE, affiliation),
+            last_name: None,
+            first_name: None,
+            affiliation: Some(affiliation.to_string()),
+            competitor_id: Some(competitor_id.to_string()),
+            time_ms: None,
+            time: None,
+            place: None,
+            status: None,
+            provisional: false,
+            withdrawn: false,
+        }
+    }
+
+    fn race(name: &str, group: &str, length: i32, round: Round, lanes: Vec<JsonLane>) -> JsonRace {
+        JsonRace {
+            name: name.to_string(),
+            title: name.to_string(),
+            event: name.trim_end_matches(char::is_alphabetic).to_string(),
+            heat: 1,
+            round,
+            group: Some(group.to_string()),
+            length: Some(length),
+            track: 111,
+            status: RaceStatus::Scheduled,
+            lanes,
+        }
+    }
+
+    fn races() -> Vec<JsonRace> {
+        vec![
+            race("1A", "Open Women", 500, Round::Heat, vec![lane("303313", "RICH"), lane("303401", "KEL")]),
+            race("1B", "Open Women", 500, Round::Heat, vec![lane("303500", "RICH")]),
+            race("2A", "Open Men", 1000, Round::SemiFinal, vec![lane("203879", "KEL")]),
+            race("3A", "Open Women", 500, Round::FinalA, vec![lane("303313", "RICH")]),
+        ]
+    }
+
+    fn published() -> WebState {
+        let state = WebState::new();
+        state.publish(races());
+        state
+    }
+
+    async fn request(state: &WebState, request: Request<Body>) -> (StatusCode, Vec<u8>) {
+        let response = router(state.clone()).oneshot(request).await.unwrap();
+        let status = response.status();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (status, body.to_vec())
+    }
+
+    async fn get_bytes(state: &WebState, uri: &str) -> (StatusCode, Vec<u8>) {
+        let get = Request::get(uri).header(header::HOST, "export-pc:3030").body(Body::empty()).unwrap();
+        request(state, get).await
+    }
+
+    async fn get(state: &WebState, uri: &str) -> (StatusCode, String) {
+        let (status, body) = get_bytes(state, uri).await;
+        (status, String::from_utf8(body).unwrap())
+    }
+
+    /// Race names of a JSON array of races, or of a single race.
+    fn names(body: &str) -> Vec<String> {
+        let value: serde_json::Value = serde_json::from_str(body).unwrap();
+        let races = match value {
+            serde_json::Value::Array(races) => races,
+            race => vec![race],
+        };
+        races.iter().map(|r| r["name"].as_str().unwrap().to_string()).collect()
+    }
+
+    #[tokio::test]
+    async fn filters_races() {
+        let state = published();
+        let cases = [
+            ("/races", vec!["1A", "1B", "2A", "3A"]),
+            ("/races?group=open%20women", vec!["1A", "1B", "3A"]),
+            ("/races?distance=1000", vec!["2A"]),
+            ("/races?round=heat", vec!["1A", "1B"]),
+            ("/races?round=Semi-final", vec!["2A"]),
+            ("/races?round=finalA&group=Open+Women&distance=500", vec!["3A"]),
+            ("/races?group=&round=", vec!["1A", "1B", "2A", "3A"]),
+            ("/races?group=Masters", vec![]),
+        ];
+        for (uri, expected) in cases {
+            let (status, body) = get(&state, uri).await;
+            assert_eq!(status, StatusCode::OK, "{}", uri);
+            assert_eq!(names(&body), expected, "{}", uri);
+        }
+
+        let (status, _) = get(&state, "/races?round=fourth").await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn finds_races_events_skaters_and_clubs() {
+        let state = published();
+        let cases = [
+            ("/races/1b", vec!["1B"]),
+            ("/events/1", vec!["1A", "1B"]),
+            ("/skaters/303313", vec!["1A", "3A"]),
+            ("/clubs/kel", vec!["1A", "2A"]),
+            ("/clubs/NONE", vec![]),
+        ];
+        for (uri, expected) in cases {
+            let (status, body) = get(&state, uri).await;
+            assert_eq!(status, StatusCode::OK, "{}", uri);
+            assert_eq!(names(&body), expected, "{}", uri);
+        }
+
+        let (status, body) = get(&state, "/races/9Z").await;
+        assert_eq!((status, body.as_str()), (StatusCode::NOT_FOUND, "No race 9Z"));
+    }
+
+    #[tokio::test]
+    async fn points_logo_urls_at_the_requested_host() {
+        let state = published();
+        let (_, body) = get(&state, "/races/2A").await;
+        let race: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(race["lanes"][0]["affiliationUrl"], "http://export-pc:3030/logos/KEL.png");
+        // The served races keep the placeholder for the next client
+        assert!(state.races.read().unwrap()[2].lanes[0].affiliation_url.contains(HOST_PLACEHOLDER));
+    }
+
+    #[tokio::test]
+    async fn serves_logos_from_the_folder() {
+        let dir = std::env::temp_dir().join(format!("gcpv-web-logos-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("KEL.png"), b"kel").unwrap();
+        std::fs::write(dir.join("default.svg"), b"<svg/>").unwrap();
+
+        let state = WebState::new();
+        let served = state.serve_logos(&LogoOptions {
+            base: dir.display().to_string(),
+            fallback: dir.join("default.svg").display().to_string(),
+            ..Default::default()
+        });
+        assert_eq!(served.base, "http://{host}/logos/{key}.png");
+
+        let (status, body) = get(&state, "/logos/KEL.png").await;
+        assert_eq!((status, body.as_str()), (StatusCode::OK, "kel"));
+        // Missing logos and names reaching outside the folder get the fallback
+        assert_eq!(get(&state, "/logos/RICH.png").await.1, "<svg/>");
+        assert_eq!(get(&state, "/logos/..%5CKEL.png").await.1, "<svg/>");
+
+        *state.logos.write().unwrap() = LogoOptions::default();
+        assert_eq!(get_bytes(&state, "/logos/KEL.png").await, (StatusCode::OK, PLACEHOLDER_PNG.to_vec()));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn publishes_only_changes() {
+        let state = WebState::new();
+        let mut updates = state.updates.subscribe();
+        assert_eq!(state.publish(races()), Some(1));
+        let first = updates.try_recv().unwrap();
+        assert_eq!((first.version, first.races.len()), (1, 4));
+        assert_eq!(first.order.as_deref().map(<[String]>::len), Some(4));
+
+        // Nothing new: no version, no update
+        assert_eq!(state.publish(races()), None);
+        assert!(updates.try_recv().is_err());
+        assert_eq!(state.version(), 1);
+
+        // One race gets a result
+        let mut changed = races();
+        changed[1].status = RaceStatus::Results;
+        changed[1].lanes[0].time = Some("44.11".to_string());
+        assert_eq!(state.publish(changed.clone()), Some(2));
+        let update = updates.try_recv().unwrap();
+        assert_eq!(update.version, 2);
+        assert_eq!(names(&serde_json::to_string(&update.races).unwrap()), ["1B"]);
+        assert!(update.removed.is_empty());
+        assert_eq!(update.order, None);
+
+        // A race is dropped and the rest reordered
+        changed.remove(0);
+        changed.swap(0, 1);
+        assert_eq!(state.publish(changed), Some(3));
+        let update = updates.try_recv().unwrap();
+        assert!(update.races.is_empty());
+        assert_eq!(update.removed, ["1A"]);
+        assert_eq!(update.order.unwrap(), ["2A", "1B", "3A"]);
+    }
+
+    /// The `event`, `id` and `data` fields of the next server-sent event.
+    async fn next_event<S>(body: &mut S) -> (String, String, serde_json::Value)
+    where
+        S: Stream<Item = Result<axum::body::Bytes, axum::Error>> + Unpin,
+    {
+        let chunk = body.next().await.unwrap().unwrap();
+        let text = String::from_utf8(chunk.to_vec()).unwrap();
+        let field = |name: &str| text.lines()
+            .find_map(|l| l.strip_prefix(name).and_then(|v| v.strip_prefix(':')))
+            .map(|v| v.trim().to_string())
+            .unwrap_or_default();
+        (field("event"), field("id"), serde_json::from_str(&field("data")).unwrap())
+    }
+
+    #[tokio::test]
+    async fn streams_snapshot_then_updates() {
+        let state = published();
+        let get = Request::get("/updates").header(header::HOST, "export-pc:3030").body(Body::empty()).unwrap();
+        let response = router(state.clone()).oneshot(get).await.unwrap();
+        assert_eq!(response.headers()[header::CONTENT_TYPE], "text/event-stream");
+        let mut body = response.into_body().into_data_stream();
+
+        let (event, id, data) = next_event(&mut body).await;
+        assert_eq!((event.as_str(), id.as_str()), ("snapshot", "1"));
+        assert_eq!(data["races"].as_array().unwrap().len(), 4);
+        assert_eq!(data["next"], "1A");
+        assert_eq!(data["races"][0]["lanes"][0]["affiliationUrl"], "http://export-pc:3030/logos/RICH.png");
+
+        let mut changed = races();
+        changed[3].title = "3A Final A".to_string();
+        state.publish(changed);
+        let (event, id, data) = next_event(&mut body).await;
+        assert_eq!((event.as_str(), id.as_str()), ("update", "2"));
+        assert_eq!(data["races"][0]["title"], "3A Final A");
+        assert!(data.get("order").is_none());
+        assert!(data.get("removed").is_none());
+    }
+
+    #[tokio::test]
+    async fn sends_a_new_snapshot_to_lagging_clients() {
+        let state = published();
+        let get = Request::get("/updates").body(Body::empty()).unwrap();
+        let response = router(state.clone()).oneshot(get).await.unwrap();
+        let mut body = response.into_body().into_data_stream();
+
+        // More changes than the buffer holds before the client reads any
+        for i in 0..UPDATE_BUFFER + 4 {
+            let mut changed = races();
+            changed[0].title = format!("1A take {}", i);
+            state.publish(changed);
+        }
+        let latest = state.version();
+
+        assert_eq!(next_event(&mut body).await.0, "snapshot");
+        let (event, id, data) = next_event(&mut body).await;
+        assert_eq!(event, "snapshot");
+        assert_eq!(id, latest.to_string());
+        assert_eq!(data["races"][0]["title"], format!("1A take {}", UPDATE_BUFFER + 3));
+    }
+
+    fn cursor(current: Option<&str>, next: Option<&str>, on_deck: Option<&str>) -> Cursor {
+        Cursor {
+            current: current.map(str::to_string),
+            next: next.map(str::to_string),
+            on_deck: on_deck.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn cursor_follows_running_order() {
+        let races = races();
+        assert_eq!(Cursor::of(&races, None), cursor(None, Some("1A"), Some("1B")));
+        assert_eq!(Cursor::of(&races, Some("1B")), cursor(Some("1B"), Some("2A"), Some("3A")));
+        assert_eq!(Cursor::of(&races, Some("2A")), cursor(Some("2A"), Some("3A"), None));
+        assert_eq!(Cursor::of(&races, Some("3A")), cursor(Some("3A"), None, None));
+        assert_eq!(Cursor::of(&[], None), cursor(None, None, None));
+    }
+
+    #[test]
+    fn sets_and_steps_the_current_race() {
+        let state = published();
+        assert_eq!(state.set_current(Some(" 2a ")).unwrap(), 2);
+        assert_eq!(state.current().as_deref(), Some("2A"));
+        assert!(state.set_current(Some("9Z")).is_err());
+        assert_eq!(state.current().as_deref(), Some("2A"));
+
+        state.step_current(1).unwrap();
+        assert_eq!(state.current().as_deref(), Some("3A"));
+        // Stops at the last and first race
+        state.step_current(5).unwrap();
+        assert_eq!(state.current().as_deref(), Some("3A"));
+        state.step_current(-10).unwrap();
+        assert_eq!(state.current().as_deref(), Some("1A"));
+
+        state.set_current(None).unwrap();
+        assert_eq!(state.current(), None);
+        state.step_current(-1).unwrap();
+        assert_eq!(state.current().as_deref(), Some("1A"));
+
+        assert!(WebState::new().step_current(1).is_err());
+    }
+
+    #[test]
+    fn moving_the_cursor_sends_an_update() {
+        let state = published();
+        let mut updates = state.updates.subscribe();
+        let version = state.step_current(1).unwrap();
+        let update = updates.try_recv().unwrap();
+        assert_eq!(update.version, version);
+        assert!(update.races.is_empty());
+        assert_eq!(update.cursor, cursor(Some("1A"), Some("1B"), Some("2A")));
+
+        // New races keep the cursor, and updates carry it
+        let mut changed = races();
+        changed.remove(1);
+        state.publish(changed);
+        let update = updates.try_recv().unwrap();
+        assert_eq!(update.cursor, cursor(Some("1A"), Some("2A"), Some("3A")));
+
+        // Removing the current race clears it
+        let mut changed = races();
+        changed.remove(0);
+        state.publish(changed);
+        assert_eq!(state.current(), None);
+        assert_eq!(updates.try_recv().unwrap().cursor, cursor(None, Some("1B"), Some("2A")));
+    }
+
+    #[test]
+    fn sends_versions_in_order() {
+        let state = published();
+        let mut updates = state.updates.subscribe();
+        let reader = std::thread::spawn(move || {
+            let mut versions = Vec::new();
+            loop {
+                match updates.blocking_recv() {
+                    Ok(update) => versions.push(update.version),
+                    Err(RecvError::Lagged(_)) => continue,
+                    Err(RecvError::Closed) => return versions,
+                }
+            }
+        });
+
+        // Two controllers and a publisher, as with the GUI, POST /control and a cycle
+        let steppers: Vec<_> = [1, -1].into_iter().map(|step| {
+            let state = state.clone();
+            std::thread::spawn(move || {
+                for _ in 0..500 {
+                    state.step_current(step).unwrap();
+                }
+            })
+        }).collect();
+        for i in 0..200 {
+            let mut changed = races();
+            changed[1].title = format!("1B take {}", i);
+            state.publish(changed);
+        }
+        for stepper in steppers {
+            stepper.join().unwrap();
+        }
+        drop(state);
+
+        let versions = reader.join().unwrap();
+        assert!(!versions.is_empty());
+        assert!(versions.windows(2).all(|w| w[0] < w[1]), "out of order: {:?}", versions);
+    }
+
+    #[tokio::test]
+    async fn controls_the_current_race_over_http() {
+        let state = published();
+        let post = |body: &str| Request::post("/control/current")
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap();
+
+        assert_eq!(get(&state, "/current").await.0, StatusCode::NOT_FOUND);
+        assert_eq!(names(&get(&state, "/next").await.1), ["1A"]);
+        assert_eq!(names(&get(&state, "/on-deck").await.1), ["1B"]);
+
+        let (status, body) = request(&state, post(r#"{"race": "1b"}"#)).await;
+        assert_eq!(status, StatusCode::OK);
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body, serde_json::json!({"current": "1B", "next": "2A", "onDeck": "3A"}));
+        assert_eq!(names(&get(&state, "/current").await.1), ["1B"]);
+
+        request(&state, post(r#"{"step": -1}"#)).await;
+        assert_eq!(state.current().as_deref(), Some("1A"));
+
+        let (status, _) = request(&state, post(r#"{"race": "9Z"}"#)).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        request(&state, post(r#"{"race": null}"#)).await;
+        assert_eq!(state.current(), None);
+    }
+}
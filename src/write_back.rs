@@ -0,0 +1,294 @@
+use crate::change::{write_atomic, FileStamp};
+use crate::lif::RaceResults;
+use crate::logic::{Competitor, CompetitorInCompetition, Lane, Race};
+use crate::race_time::RaceTime;
+use anyhow::{Context, Result};
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// A change to one TPatVagues row, keyed by CleTPatVagues.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResultUpdate {
+    pub lane_id: i32,
+    pub race: String,
+    pub helmet_id: Option<i32>,
+    pub skater: String,
+    pub old_time: Option<String>,
+    /// GCPV time value, minutes.secondshundredths (44.11s is 0.4411)
+    pub new_time: f64,
+    pub old_rank: Option<i32>,
+    pub new_rank: i32,
+}
+
+impl fmt::Display for ResultUpdate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let helmet = self.helmet_id.map(|h| h.to_string()).unwrap_or_else(|| "-".to_string());
+        let old_rank = self.old_rank.map(|r| r.to_string()).unwrap_or_else(|| "-".to_string());
+        write!(f, "{} #{} {}: Temps {} -> {:.4}, Rang {} -> {}",
+            self.race,
+            helmet,
+            self.skater,
+            self.old_time.as_deref().unwrap_or("-"),
+            self.new_time,
+            old_rank,
+            self.new_rank
+        )
+    }
+}
+
+/// What a write-back would change, shown to the operator before anything is written.
+#[derive(Debug, Clone, Default)]
+pub struct WriteBackPlan {
+    pub updates: Vec<ResultUpdate>,
+    /// Results that can't be written, e.g. DNF rows or unparseable times.
+    pub skipped: Vec<String>,
+    /// The .pat file as it was when planned; the plan is stale once it changes.
+    pub source_stamp: Option<FileStamp>,
+}
+
+impl WriteBackPlan {
+    pub fn is_empty(&self) -> bool {
+        self.updates.is_empty()
+    }
+}
+
+impl fmt::Display for WriteBackPlan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.updates.is_empty() {
+            writeln!(f, "No changes to write")?;
+        }
+        for update in &self.updates {
+            writeln!(f, "{}", update)?;
+        }
+        for skipped in &self.skipped {
+            writeln!(f, "Skipped: {}", skipped)?;
+        }
+        Ok(())
+    }
+}
+
+/// Compares imported results with the times and ranks already in GCPV.
+pub fn plan_updates(
+    races: &[Race],
+    lanes: &[Lane],
+    competitors: &[Competitor],
+    competitors_in_comp: &[CompetitorInCompetition],
+    results: &RaceResults
+) -> WriteBackPlan {
+    let race_map: HashMap<i32, &Race> = races.iter().map(|r| (r.id, r)).collect();
+    let comp_in_comp_map: HashMap<i32, &CompetitorInCompetition> = competitors_in_comp.iter().map(|c| (c.id, c)).collect();
+    let comp_map: HashMap<String, &Competitor> = competitors.iter().filter_map(|c| c.id.as_ref().map(|id| (id.clone(), c))).collect();
+
+    let mut plan = WriteBackPlan::default();
+    for lane in lanes {
+        let Some(result) = results.for_lane(lane.id) else {
+            continue;
+        };
+        let race = race_map.get(&lane.race_id).map(|r| r.name.as_str()).unwrap_or("?");
+        let competitor_in_comp = comp_in_comp_map.get(&lane.skater_in_competition_id);
+        let comp_info = competitor_in_comp.and_then(|cic| cic.competitor_id.as_ref().and_then(|cid| comp_map.get(cid)));
+        let skater = comp_info
+            .map(|c| format!("{} {}", c.first_name.as_deref().unwrap_or(""), c.last_name.as_deref().unwrap_or("")).trim().to_string())
+            .unwrap_or_default();
+        let helmet_id = competitor_in_comp.and_then(|c| c.helmet_id);
+
+        if let Some(code) = result.time.and_then(|t| t.code()) {
+            plan.skipped.push(format!("{} {}: {} is entered in GCPV by hand", race, skater, code.as_str()));
+            continue;
+        }
+        let (Some(place), Some(new_time)) = (result.place, result.time.and_then(|t| t.to_gcpv())) else {
+            plan.skipped.push(format!("{} {}: no place or time", race, skater));
+            continue;
+        };
+
+        // Compare at GCPV's precision, hundredths
+        let old = lane.time.as_deref().and_then(RaceTime::from_gcpv);
+        let same_time = old.and_then(|t| t.to_gcpv()) == Some(new_time);
+        if same_time && lane.position == Some(place) {
+            continue;
+        }
+
+        plan.updates.push(ResultUpdate {
+            lane_id: lane.id,
+            race: race.to_string(),
+            helmet_id,
+            skater,
+            old_time: lane.time.clone(),
+            new_time,
+            old_rank: lane.position,
+            new_rank: place,
+        });
+    }
+    plan
+}
+
+/// Somewhere TPatVagues can be updated.
+pub trait ResultWriter {
+    /// Files `apply` modifies; each is backed up before writing.
+    fn files(&self) -> Vec<PathBuf>;
+    fn apply(&self, updates: &[ResultUpdate]) -> Result<()>;
+}
+
+/// Backs up the writer's files, then applies the updates. Returns the backups.
+pub fn write_results<W: ResultWriter>(writer: &W, updates: &[ResultUpdate]) -> Result<Vec<PathBuf>> {
+    let files = writer.files();
+    if files.is_empty() {
+        anyhow::bail!("Nothing to back up, not writing");
+    }
+    let backups = files
+        .iter()
+        .map(|f| backup_file(f))
+        .collect::<Result<Vec<_>>>()?;
+    writer.apply(updates)?;
+    Ok(backups)
+}
+
+/// Copies `path` next to itself as `<stem>-backup-<timestamp>.<ext>`, with
+/// milliseconds in the timestamp. Never overwrites an existing backup.
+pub fn backup_file(path: &Path) -> Result<PathBuf> {
+    let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or("backup");
+    let stamp = chrono::Local::now().format("%Y%m%d-%H%M%S-%3f");
+    let backup = match path.extension().and_then(|e| e.to_str()) {
+        Some(ext) => path.with_file_name(format!("{}-backup-{}.{}", stem, stamp, ext)),
+        None => path.with_file_name(format!("{}-backup-{}", stem, stamp)),
+    };
+    copy_new(path, &backup)
+        .with_context(|| format!("Failed to back up {} to {}", path.display(), backup.display()))?;
+    if std::fs::metadata(&backup)?.len() != std::fs::metadata(path)?.len() {
+        anyhow::bail!("Backup {} is incomplete", backup.display());
+    }
+    Ok(backup)
+}
+
+/// Like `fs::copy`, but fails if `to` already exists.
+fn copy_new(from: &Path, to: &Path) -> Result<()> {
+    let mut source = std::fs::File::open(from)?;
+    let mut target = std::fs::OpenOptions::new().write(true).create_new(true).open(to)?;
+    std::io::copy(&mut source, &mut target)?;
+    target.sync_all()?;
+    Ok(())
+}
+
+/// Updates a .pat file through the Access ODBC driver (Windows only).
+pub struct OdbcResultWriter {
+    file_path: String,
+}
+
+impl OdbcResultWriter {
+    pub fn new(file_path: &str) -> Self {
+        Self { file_path: file_path.to_string() }
+    }
+}
+
+impl ResultWriter for OdbcResultWriter {
+    fn files(&self) -> Vec<PathBuf> {
+        vec![PathBuf::from(&self.file_path)]
+    }
+
+    fn apply(&self, updates: &[ResultUpdate]) -> Result<()> {
+        crate::db::update_pat_vagues(&self.file_path, updates)
+    }
+}
+
+/// Rounded to GCPV's precision, so 0.4411 isn't written as 0.44110000000000005.
+fn gcpv_time(time: f64) -> f64 {
+    format!("{:.4}", time).parse().unwrap_or(time)
+}
+
+/// Updates the TPatVagues table of a fixture directory (see `source::FixtureSource`).
+pub struct FixtureResultWriter {
+    dir: PathBuf,
+}
+
+impl FixtureResultWriter {
+    pub fn new<P: AsRef<Path>>(dir: P) -> Self {
+        Self { dir: dir.as_ref().to_path_buf() }
+    }
+
+    fn table_file(&self) -> PathBuf {
+        let json_path = self.dir.join("TPatVagues.json");
+        if json_path.exists() {
+            json_path
+        } else {
+            self.dir.join("TPatVagues.csv")
+        }
+    }
+}
+
+impl ResultWriter for FixtureResultWriter {
+    fn files(&self) -> Vec<PathBuf> {
+        vec![self.table_file()]
+    }
+
+    fn apply(&self, updates: &[ResultUpdate]) -> Result<()> {
+        let path = self.table_file();
+        let by_lane: HashMap<i32, &ResultUpdate> = updates.iter().map(|u| (u.lane_id, u)).collect();
+
+        if path.extension().and_then(|e| e.to_str()) == Some("json") {
+            let file = std::fs::File::open(&path)?;
+            let mut rows: Vec<serde_json::Map<String, serde_json::Value>> = serde_json::from_reader(std::io::BufReader::new(file))
+                .with_context(|| format!("Failed to deserialize {}", path.display()))?;
+            for row in rows.iter_mut() {
+                let key = row.get("CleTPatVagues").and_then(|v| v.as_i64());
+                if let Some(update) = key.and_then(|k| by_lane.get(&(k as i32))) {
+                    row.insert("Temps".to_string(), serde_json::Value::from(gcpv_time(update.new_time)));
+                    row.insert("Rang".to_string(), serde_json::Value::from(update.new_rank));
+                }
+            }
+            write_atomic(&path, serde_json::to_string_pretty(&rows)?.as_bytes())?;
+            return Ok(());
+        }
+
+        let mut reader = csv::Reader::from_path(&path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        let headers = reader.headers()?.clone();
+        let column = |name: &str| headers.iter().position(|h| h == name)
+            .with_context(|| format!("{} has no {} column", path.display(), name));
+        let (key_col, time_col, rank_col) = (column("CleTPatVagues")?, column("Temps")?, column("Rang")?);
+
+        let mut rows = Vec::new();
+        for record in reader.records() {
+            let record = record?;
+            let key = record.get(key_col).and_then(|k| k.trim().parse::<i32>().ok());
+            let mut fields: Vec<String> = record.iter().map(|f| f.to_string()).collect();
+            if let Some(update) = key.and_then(|k| by_lane.get(&k)) {
+                fields[time_col] = format!("{:.4}", update.new_time);
+                fields[rank_col] = update.new_rank.to_string();
+            }
+            rows.push(fields);
+        }
+
+        let mut writer = csv::Writer::from_writer(Vec::new());
+        writer.write_record(&headers)?;
+        for row in rows {
+            writer.write_record(&row)?;
+        }
+        write_atomic(&path, &writer.into_inner()?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backs_up_without_overwriting() {
+        let dir = std::env::temp_dir().join(format!("gcpv-backup-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let pat = dir.join("comp.pat");
+        std::fs::write(&pat, "first").unwrap();
+
+        let backup = backup_file(&pat).unwrap();
+        assert_eq!(std::fs::read_to_string(&backup).unwrap(), "first");
+        let name = backup.file_name().unwrap().to_str().unwrap();
+        // comp-backup-YYYYMMDD-HHMMSS-mmm.pat
+        assert!(name.starts_with("comp-backup-") && name.ends_with(".pat") && name.len() == "comp-backup-20261017-101112-123.pat".len(), "{}", name);
+
+        // A second backup in the same millisecond fails rather than replacing the first
+        std::fs::write(&pat, "second").unwrap();
+        assert!(copy_new(&pat, &backup).is_err());
+        assert_eq!(std::fs::read_to_string(&backup).unwrap(), "first");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
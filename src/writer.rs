@@ -1,11 +1,83 @@
 use crate::logic::{Race, Lane, ProgramItem, CompetitorInCompetition, Competitor};
 use anyhow::Result;
-use std::fs::File;
-use std::io::Write;
-use serde::Serialize;
+use crate::change::write_atomic;
+use std::fmt::Write as _;
+use serde::{Deserialize, Serialize};
 use crate::logic::letter_to_number;
+use crate::race_time::{RaceTime, TimePrecision};
+use crate::round::Round;
+use crate::logos::LogoOptions;
+use crate::text::{NameOptions, OutputEncoding};
 
-#[derive(Serialize, Clone)]
+/// What to do with skaters marked withdrawn (TPatineur_compe.Retirer).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum WithdrawnPolicy {
+    /// Leave them out of the heats.
+    #[default]
+    Omit,
+    /// Keep them, marked as withdrawn.
+    Flag,
+    /// Keep them as if they were skating.
+    Include,
+}
+
+impl WithdrawnPolicy {
+    pub const ALL: [WithdrawnPolicy; 3] = [WithdrawnPolicy::Omit, WithdrawnPolicy::Flag, WithdrawnPolicy::Include];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            WithdrawnPolicy::Omit => "Omit",
+            WithdrawnPolicy::Flag => "Include flagged",
+            WithdrawnPolicy::Include => "Include",
+        }
+    }
+}
+
+impl std::str::FromStr for WithdrawnPolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "omit" => Ok(WithdrawnPolicy::Omit),
+            "flag" | "flagged" => Ok(WithdrawnPolicy::Flag),
+            "include" => Ok(WithdrawnPolicy::Include),
+            other => Err(anyhow::anyhow!("Unknown withdrawn policy '{}' (expected omit, flag or include)", other)),
+        }
+    }
+}
+
+/// Appended to the last name of flagged withdrawn skaters in LYNX.EVT and LYNX.PPL.
+pub const WITHDRAWN_MARKER: &str = "(WD)";
+
+/// Settings shared by the exporters.
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ExportOptions {
+    pub withdrawn: WithdrawnPolicy,
+    pub logos: LogoOptions,
+    pub names: NameOptions,
+    /// Applies to the Lynx files; JSON is always UTF-8.
+    pub encoding: OutputEncoding,
+    /// Decimals of the JSON `time` strings; `timeMs` is always exact.
+    pub precision: TimePrecision,
+    /// Folder of FinishLynx .LIF files. Their results fill in lanes that have
+    /// none in GCPV yet; empty to use GCPV results only.
+    pub results_folder: String,
+}
+
+impl ExportOptions {
+    /// Whether a skater is listed in the heats, and whether they are flagged.
+    fn lane_visibility(&self, competitor_in_comp: Option<&CompetitorInCompetition>) -> Option<bool> {
+        let withdrawn = competitor_in_comp.and_then(|c| c.removed).unwrap_or(false);
+        match (withdrawn, self.withdrawn) {
+            (false, _) | (true, WithdrawnPolicy::Include) => Some(false),
+            (true, WithdrawnPolicy::Flag) => Some(true),
+            (true, WithdrawnPolicy::Omit) => None,
+        }
+    }
+}
+
+#[derive(Serialize, Clone, PartialEq)]
 #[serde(rename_all = "camelCase")]
 pub struct JsonLane {
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -22,20 +94,48 @@ pub struct JsonLane {
     pub affiliation: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub competitor_id: Option<String>,
+    /// Finish time in milliseconds
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub time_ms: Option<u64>,
+    /// Finish time as shown on the results, e.g. "44.11" or "1:02.44"
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub time: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub place: Option<i32>,
+    /// DNS, DNF, DQ or PEN
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status: Option<String>,
+    /// The result comes from Lynx and isn't in GCPV yet
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub provisional: bool,
+    /// Only set when withdrawn skaters are included flagged
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub withdrawn: bool,
 }
 
-#[derive(Serialize, Clone)]
+#[derive(Serialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum RaceStatus {
+    /// No lane has a time, place or status yet
+    Scheduled,
+    /// At least one lane has a result
+    Results,
+}
+
+#[derive(Serialize, Clone, PartialEq)]
 #[serde(rename_all = "camelCase")]
 pub struct JsonRace {
     pub name: String,
     pub title: String,
     pub event: String,
     pub heat: i32,
+    pub round: Round,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub group: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub length: Option<i32>,
     pub track: i32,
+    pub status: RaceStatus,
     pub lanes: Vec<JsonLane>,
 }
 
@@ -44,7 +144,8 @@ pub fn generate_race_json(
     programs: &[ProgramItem], 
     lanes: &[Lane], 
     competitors: &[Competitor], 
-    competitors_in_comp: &[CompetitorInCompetition]
+    competitors_in_comp: &[CompetitorInCompetition],
+    options: &ExportOptions
 ) -> Result<Vec<JsonRace>> {
     let mut json_races = Vec::new();
 
@@ -76,38 +177,60 @@ pub fn generate_race_json(
             let skater_comp_id = lane.skater_in_competition_id;
             let competitor_in_comp = comp_in_comp_map.get(&skater_comp_id);
             
+            let Some(withdrawn) = options.lane_visibility(competitor_in_comp.copied()) else {
+                continue;
+            };
+            
             let comp_info = competitor_in_comp.and_then(|cic| {
                 cic.competitor_id.as_ref().and_then(|cid| comp_map.get(cid))
             });
 
             // JSON Lane
-            let aff_url = format!("C:/Users/Goud/Desktop/SpeedSkating/logos/provinces/{}.png", 
-                competitor_in_comp.and_then(|c| c.affiliation.as_ref()).map(|s| s.as_str()).unwrap_or("")
+            let aff_url = options.logos.resolve(
+                competitor_in_comp.and_then(|c| c.affiliation.as_deref()),
+                competitor_in_comp.and_then(|c| c.club_id),
+                competitor_in_comp.and_then(|c| c.region.as_deref())
             );
             
-            let first_name = comp_info.and_then(|c| c.first_name.as_ref()).map(|s| s.as_str()).unwrap_or("");
-            let last_name = comp_info.and_then(|c| c.last_name.as_ref()).map(|s| s.as_str()).unwrap_or("");
+            let first_name = comp_info.and_then(|c| c.first_name.as_deref()).map(|s| options.names.clean(s));
+            let last_name = comp_info.and_then(|c| c.last_name.as_deref()).map(|s| options.names.last_name(s));
+            let gcpv_time = lane.time.as_deref().and_then(RaceTime::from_gcpv);
+            // GCPV uses rank 0 for "not ranked yet"
+            let gcpv_place = lane.position.filter(|p| *p > 0);
+            let lynx = lane.lynx_result.as_ref()
+                .filter(|r| gcpv_time.is_none() && gcpv_place.is_none() && (r.time.is_some() || r.place.is_some()));
+            let time = gcpv_time.or(lynx.and_then(|r| r.time));
 
             json_lanes_vec.push(JsonLane {
                 start_position: lane.start_position,
                 helmet_id: competitor_in_comp.and_then(|c| c.helmet_id),
-                name: format!("{} {}", first_name, last_name).trim().to_string(),
+                name: format!("{} {}", first_name.as_deref().unwrap_or(""), last_name.as_deref().unwrap_or("")).trim().to_string(),
                 affiliation_url: aff_url,
-                last_name: comp_info.and_then(|c| c.last_name.clone()),
-                first_name: comp_info.and_then(|c| c.first_name.clone()),
-                affiliation: competitor_in_comp.and_then(|c| c.affiliation.clone()),
+                last_name,
+                first_name,
+                affiliation: competitor_in_comp.and_then(|c| c.affiliation.as_deref()).map(|s| options.names.clean(s)),
                 competitor_id: comp_info.and_then(|c| c.id.clone()),
+                time_ms: time.and_then(|t| t.millis()),
+                time: time.and_then(|t| t.format_time(options.precision)),
+                place: gcpv_place.or(lynx.and_then(|r| r.place)),
+                status: time.and_then(|t| t.code()).map(|c| c.as_str().to_string()),
+                provisional: lynx.is_some(),
+                withdrawn,
             });
         }
 
+        let has_results = json_lanes_vec.iter().any(|l| l.time_ms.is_some() || l.place.is_some() || l.status.is_some());
+
         json_races.push(JsonRace {
             name: race.name.clone(),
-            title: format!("{} - {}m  {} ({}m)", race.name, length_val, group_str, track_val),
+            title: format!("{} - {}m  {} {} ({}m)", race.name, length_val, group_str, race.round.label(), track_val),
             event: event_name,
             heat,
+            round: race.round,
             group: program_item.and_then(|p| p.group.clone()),
             length: program_item.and_then(|p| p.length),
             track: track_val,
+            status: if has_results { RaceStatus::Results } else { RaceStatus::Scheduled },
             lanes: json_lanes_vec,
         });
     }
@@ -124,17 +247,36 @@ pub fn write_lynx_evt(
     competitors: &[Competitor], 
     competitors_in_comp: &[CompetitorInCompetition]
 ) -> Result<()> {
-    
-    // Prepare writers
-    let mut evt_file = File::create(file_path)?;
-    
-    // Generate JSON structure locally for EVT iteration (redundant but simpler for now or we just rewrite EVT logic too)
-    // Actually, EVT logic is separate. We can keep EVT writing logic here, but JSON logic is extracted.
-    // The previous implementation mixed them.
-    // Let's re-implement EVT writing loop separately because the JSON generation loop didn't write EVT.
-    
-    // OR we just iterate again for EVT.
-    
+    let options = ExportOptions::default();
+    let evt = render_lynx_evt(races, programs, lanes, competitors, competitors_in_comp, &options)?;
+    let json = render_race_json(races, programs, lanes, competitors, competitors_in_comp, &options)?;
+    write_atomic(file_path, &options.encoding.encode(&evt))?;
+    write_atomic(json_path, json.as_bytes())?;
+    Ok(())
+}
+
+pub fn render_race_json(
+    races: &[Race], 
+    programs: &[ProgramItem], 
+    lanes: &[Lane], 
+    competitors: &[Competitor], 
+    competitors_in_comp: &[CompetitorInCompetition],
+    options: &ExportOptions
+) -> Result<String> {
+    let json_races = generate_race_json(races, programs, lanes, competitors, competitors_in_comp, options)?;
+    Ok(serde_json::to_string_pretty(&json_races)?)
+}
+
+pub fn render_lynx_evt(
+    races: &[Race], 
+    programs: &[ProgramItem], 
+    lanes: &[Lane], 
+    competitors: &[Competitor], 
+    competitors_in_comp: &[CompetitorInCompetition],
+    options: &ExportOptions
+) -> Result<String> {
+    let mut evt = String::new();
+
     // Maps for faster lookup
     let program_map: std::collections::HashMap<i32, &ProgramItem> = programs.iter().map(|p| (p.id, p)).collect();
     let comp_in_comp_map: std::collections::HashMap<i32, &CompetitorInCompetition> = competitors_in_comp.iter().map(|c| (c.id, c)).collect();
@@ -147,8 +289,11 @@ pub fn write_lynx_evt(
         let track_val = program_item.map(|p| p.track).unwrap_or(100);
         let group_str = program_item.and_then(|p| p.group.as_ref()).map(|s| s.as_str()).unwrap_or("");
         
-        writeln!(evt_file, "{},1,01,{} {} {}m {}m", 
-            race.name, 
+        let (event, round, heat) = lynx_event_id(race);
+        writeln!(evt, "{},{},{:02},{} {} {}m {}m", 
+            event, 
+            round, 
+            heat, 
             race.name, 
             group_str, 
             length_val, 
@@ -163,6 +308,9 @@ pub fn write_lynx_evt(
         for lane in race_lanes {
             let skater_comp_id = lane.skater_in_competition_id;
             let competitor_in_comp = comp_in_comp_map.get(&skater_comp_id);
+            let Some(withdrawn) = options.lane_visibility(competitor_in_comp.copied()) else {
+                continue;
+            };
             
             let comp_info = competitor_in_comp.and_then(|cic| {
                 cic.competitor_id.as_ref().and_then(|cid| comp_map.get(cid))
@@ -170,21 +318,75 @@ pub fn write_lynx_evt(
 
             let helmet = competitor_in_comp.and_then(|c| c.helmet_id).unwrap_or(0);
             let start_pos = lane.start_position.unwrap_or(0);
-            let last_name = comp_info.and_then(|c| c.last_name.as_ref()).map(|s| s.as_str()).unwrap_or("");
-            let first_name = comp_info.and_then(|c| c.first_name.as_ref()).map(|s| s.as_str()).unwrap_or("");
-            let affiliation = competitor_in_comp.and_then(|c| c.affiliation.as_ref()).map(|s| s.as_str()).unwrap_or("");
+            let mut last_name = options.names.last_name(comp_info.and_then(|c| c.last_name.as_deref()).unwrap_or(""));
+            if withdrawn {
+                last_name = format!("{} {}", last_name, WITHDRAWN_MARKER);
+            }
+            let first_name = options.names.clean(comp_info.and_then(|c| c.first_name.as_deref()).unwrap_or(""));
+            let affiliation = options.names.clean(competitor_in_comp.and_then(|c| c.affiliation.as_deref()).unwrap_or(""));
             let comp_id_str = comp_info.and_then(|c| c.id.as_ref()).map(|s| s.as_str()).unwrap_or("");
 
-            writeln!(evt_file, ",{},{},{},{},{},,{}", 
+            writeln!(evt, ",{},{},{},{},{},,{}", 
                 helmet, start_pos, last_name, first_name, affiliation, comp_id_str
             )?;
         }
     }
 
-    // Write JSON using the shared function
-    let json_races = generate_race_json(races, programs, lanes, competitors, competitors_in_comp)?;
-    let json_file = File::create(json_path)?;
-    serde_json::to_writer_pretty(json_file, &json_races)?;
+    Ok(evt)
+}
 
-    Ok(())
+/// The (event, round, heat) Lynx knows a race by. Each race is its own Lynx event,
+/// so the EVT and SCH files must agree on this.
+pub fn lynx_event_id(race: &Race) -> (String, i32, i32) {
+    (race.name.clone(), race.round.lynx_number(), 1)
+}
+
+/// FinishLynx schedule file: one `event,round,heat` line per race, in running order.
+pub fn render_lynx_sch(races: &[Race]) -> Result<String> {
+    let mut sch = String::new();
+    for race in races {
+        let (event, round, heat) = lynx_event_id(race);
+        writeln!(sch, "{},{},{}", event, round, heat)?;
+    }
+    Ok(sch)
+}
+
+/// FinishLynx people file: one `ID,Last,First,Affiliation,License` line per
+/// skater in the competition, ordered by helmet number.
+pub fn render_lynx_ppl(
+    competitors: &[Competitor], 
+    competitors_in_comp: &[CompetitorInCompetition],
+    options: &ExportOptions
+) -> Result<String> {
+    let mut ppl = String::new();
+    let names = &options.names;
+
+    let comp_map: std::collections::HashMap<String, &Competitor> = competitors.iter().filter_map(|c| c.id.as_ref().map(|id| (id.clone(), c))).collect();
+
+    let mut people: Vec<&CompetitorInCompetition> = competitors_in_comp.iter().collect();
+    // Skaters without a helmet go last
+    people.sort_by_key(|c| (c.helmet_id.is_none(), c.helmet_id, c.id));
+
+    for competitor_in_comp in people {
+        // Same withdrawn policy as the heats, so Lynx can't look up a skater the EVT left out
+        let Some(withdrawn) = options.lane_visibility(Some(competitor_in_comp)) else {
+            continue;
+        };
+        let comp_info = competitor_in_comp.competitor_id.as_ref().and_then(|cid| comp_map.get(cid));
+
+        let helmet = competitor_in_comp.helmet_id.map(|h| h.to_string()).unwrap_or_default();
+        let mut last_name = names.last_name(comp_info.and_then(|c| c.last_name.as_deref()).unwrap_or(""));
+        if withdrawn {
+            last_name = format!("{} {}", last_name, WITHDRAWN_MARKER);
+        }
+        let first_name = names.clean(comp_info.and_then(|c| c.first_name.as_deref()).unwrap_or(""));
+        let affiliation = names.clean(competitor_in_comp.affiliation.as_deref().unwrap_or(""));
+        let comp_id_str = competitor_in_comp.competitor_id.as_deref().unwrap_or("");
+
+        writeln!(ppl, "{},{},{},{},{}", 
+            helmet, last_name, first_name, affiliation, comp_id_str
+        )?;
+    }
+
+    Ok(ppl)
 }
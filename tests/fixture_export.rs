@@ -0,0 +1,141 @@
+// Runs the export pipeline on the saved tables in tests/fixtures/small and
+// compares the files with tests/fixtures/small-expected.
+
+use rust_gcpv_lynx_export::app_logic::{build_export_data, build_race_data, execute_cycle, CycleOutcome, OutputPaths};
+use rust_gcpv_lynx_export::logic::Snapshot;
+use rust_gcpv_lynx_export::race_time::TimePrecision;
+use rust_gcpv_lynx_export::source::{DataSource, FixtureSource};
+use rust_gcpv_lynx_export::writer::{render_lynx_evt, render_lynx_ppl, render_race_json, ExportOptions, WithdrawnPolicy};
+use std::path::{Path, PathBuf};
+
+fn fixture() -> FixtureSource {
+    FixtureSource::new(Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/small"))
+}
+
+fn expected(name: &str) -> String {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/small-expected").join(name);
+    // Tolerate a checkout that converted line endings
+    std::fs::read_to_string(path).unwrap().replace("\r\n", "\n")
+}
+
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("gcpv-lynx-export-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[test]
+fn lists_fixture_tables() {
+    let tables = fixture().list_tables().unwrap();
+    assert_eq!(tables, [
+        "TClubs", "TCompetition", "TDistances_Standards", "TPatVagues", "TPatineur_compe",
+        "TPatineurs", "TProg_Courses", "TRegions", "TVagues",
+    ]);
+}
+
+#[test]
+fn reads_numeric_and_text_times() {
+    let snapshot = Snapshot::load(&fixture()).unwrap();
+    let temps = |lane: i32| snapshot.pat_vagues.iter().find(|l| l.cle_tpat_vagues == Some(lane)).unwrap().temps.clone();
+    assert_eq!(temps(5001).as_deref(), Some("0.4411"));
+    assert_eq!(temps(5002).as_deref(), Some("0.4523"));
+    assert_eq!(temps(5003), None);
+    // Temps left out of the row entirely
+    assert_eq!(temps(5005), None);
+}
+
+#[test]
+fn renders_lynx_evt() {
+    let snapshot = Snapshot::load(&fixture()).unwrap();
+    let race_data = build_race_data(&snapshot, None).unwrap();
+    let evt = render_lynx_evt(
+        &race_data.races,
+        &race_data.programs,
+        &race_data.lanes,
+        &race_data.competitors,
+        &race_data.competitors_in_comp,
+        &ExportOptions::default()
+    ).unwrap();
+    assert_eq!(evt, expected("LYNX.EVT"));
+}
+
+#[test]
+fn writes_every_output() {
+    let dir = temp_dir("fixture");
+    let outputs = OutputPaths::in_folder(&dir);
+    let outcome = execute_cycle(&fixture(), &outputs, &ExportOptions::default(), None).unwrap();
+    assert_eq!(outcome, CycleOutcome::Updated);
+    for name in ["LYNX.EVT", "LYNX.PPL", "LYNX.SCH", "races.json"] {
+        let written = std::fs::read_to_string(dir.join(name)).unwrap();
+        assert_eq!(written, expected(name), "{} differs", name);
+    }
+
+    // Same input again: nothing is rewritten
+    let outcome = execute_cycle(&fixture(), &outputs, &ExportOptions::default(), None).unwrap();
+    assert_eq!(outcome, CycleOutcome::Unchanged);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn fills_missing_results_from_lynx() {
+    let snapshot = Snapshot::load(&fixture()).unwrap();
+    let options = ExportOptions {
+        results_folder: Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/small-lynx").display().to_string(),
+        ..Default::default()
+    };
+    let race_data = build_export_data(&snapshot, &options, None).unwrap();
+    let json = render_race_json(
+        &race_data.races,
+        &race_data.programs,
+        &race_data.lanes,
+        &race_data.competitors,
+        &race_data.competitors_in_comp,
+        &options
+    ).unwrap();
+    let races: serde_json::Value = serde_json::from_str(&json).unwrap();
+    let lane = |race: usize, lane: usize| races[race]["lanes"][lane].clone();
+
+    // GCPV's time wins over Lynx's
+    assert_eq!(lane(1, 0)["time"], "1:02.44");
+    assert_eq!(lane(1, 0).get("provisional"), None);
+    // No result in GCPV yet
+    assert_eq!(lane(1, 1)["time"], "1:05.00");
+    assert_eq!(lane(1, 1)["place"], 2);
+    assert_eq!(lane(1, 1)["provisional"], true);
+    // Matched by lane, Lynx has no helmet
+    assert_eq!(lane(2, 0)["status"], "DNF");
+    assert_eq!(lane(2, 0)["provisional"], true);
+}
+
+#[test]
+fn formats_times_with_precision() {
+    let snapshot = Snapshot::load(&fixture()).unwrap();
+    let race_data = build_race_data(&snapshot, None).unwrap();
+    let options = ExportOptions { precision: TimePrecision::Tenths, ..Default::default() };
+    let json = render_race_json(
+        &race_data.races,
+        &race_data.programs,
+        &race_data.lanes,
+        &race_data.competitors,
+        &race_data.competitors_in_comp,
+        &options
+    ).unwrap();
+    let races: serde_json::Value = serde_json::from_str(&json).unwrap();
+    assert_eq!(races[0]["lanes"][0]["time"], "44.1");
+    assert_eq!(races[0]["lanes"][0]["timeMs"], 44110);
+    assert_eq!(races[1]["lanes"][0]["time"], "1:02.4");
+}
+
+#[test]
+fn applies_withdrawn_policy_to_people() {
+    let snapshot = Snapshot::load(&fixture()).unwrap();
+    let race_data = build_race_data(&snapshot, None).unwrap();
+    let ppl = |withdrawn: WithdrawnPolicy| {
+        let options = ExportOptions { withdrawn, ..Default::default() };
+        render_lynx_ppl(&race_data.competitors, &race_data.competitors_in_comp, &options).unwrap()
+    };
+    assert_eq!(ppl(WithdrawnPolicy::Omit), expected("LYNX.PPL"));
+    assert!(ppl(WithdrawnPolicy::Flag).contains("13,SMITH (WD),Anna,RICH,310022\n"));
+    assert!(ppl(WithdrawnPolicy::Include).contains("13,SMITH,Anna,RICH,310022\n"));
+}
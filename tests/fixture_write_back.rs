@@ -0,0 +1,66 @@
+// Writes the results in tests/fixtures/small-lynx to a copy of the
+// tests/fixtures/small tables, the way "Write to .pat" does.
+
+use rust_gcpv_lynx_export::app_logic::{apply_write_back, plan_snapshot_write_back};
+use rust_gcpv_lynx_export::change::FileStamp;
+use rust_gcpv_lynx_export::logic::Snapshot;
+use rust_gcpv_lynx_export::source::FixtureSource;
+use rust_gcpv_lynx_export::write_back::FixtureResultWriter;
+use std::path::{Path, PathBuf};
+
+fn fixture_copy(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("gcpv-write-back-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    for entry in std::fs::read_dir(Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/small")).unwrap() {
+        let path = entry.unwrap().path();
+        std::fs::copy(&path, dir.join(path.file_name().unwrap())).unwrap();
+    }
+    dir
+}
+
+fn lynx_results() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/small-lynx")
+}
+
+#[test]
+fn writes_lynx_results_to_fixture() {
+    let dir = fixture_copy("apply");
+    let table = dir.join("TPatVagues.json");
+    let snapshot = Snapshot::load(&FixtureSource::new(&dir)).unwrap();
+    let mut plan = plan_snapshot_write_back(&snapshot, lynx_results(), None).unwrap();
+    plan.source_stamp = FileStamp::of(&table).ok();
+
+    let lanes: Vec<(i32, f64, i32)> = plan.updates.iter().map(|u| (u.lane_id, u.new_time, u.new_rank)).collect();
+    assert_eq!(lanes, [(5004, 1.019, 1), (5005, 1.05, 2)]);
+    // The DNF is left for GCPV
+    assert_eq!(plan.skipped.len(), 1);
+
+    let backups = apply_write_back(&FixtureResultWriter::new(&dir), &plan).unwrap();
+    assert_eq!(backups.len(), 1);
+    assert!(backups[0].exists());
+
+    let snapshot = Snapshot::load(&FixtureSource::new(&dir)).unwrap();
+    let row = |lane: i32| snapshot.pat_vagues.iter().find(|l| l.cle_tpat_vagues == Some(lane)).unwrap();
+    assert_eq!((row(5004).temps.as_deref(), row(5004).rang), (Some("1.019"), Some(1)));
+    assert_eq!((row(5005).temps.as_deref(), row(5005).rang), (Some("1.05"), Some(2)));
+    // Other rows are untouched
+    assert_eq!((row(5001).temps.as_deref(), row(5001).rang), (Some("0.4411"), Some(1)));
+    assert_eq!(row(5003).temps, None);
+
+    // Written results now match Lynx, so there is nothing left to write
+    let plan = plan_snapshot_write_back(&snapshot, lynx_results(), None).unwrap();
+    assert!(plan.is_empty());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn refuses_stale_plan() {
+    let dir = fixture_copy("stale");
+    let snapshot = Snapshot::load(&FixtureSource::new(&dir)).unwrap();
+    // No stamp from the preview, so the table may have changed since
+    let plan = plan_snapshot_write_back(&snapshot, lynx_results(), None).unwrap();
+    assert!(plan.source_stamp.is_none());
+    assert!(apply_write_back(&FixtureResultWriter::new(&dir), &plan).is_err());
+    std::fs::remove_dir_all(&dir).unwrap();
+}